@@ -0,0 +1,46 @@
+use crate::doveadm::{FetchRecord, ImapField};
+use std::fmt::Display;
+
+mod age;
+pub use age::{AgeReport, AgeTotals};
+
+mod attachments;
+pub use attachments::{Attachment, AttachmentReport, AttachmentSummary, AttachmentTotals};
+
+mod duplicates;
+pub use duplicates::{DuplicateMessage, DuplicateReport, DuplicateSet, DuplicateSummary};
+
+mod folders;
+pub use folders::FolderReport;
+
+mod lists;
+pub use lists::{ListReport, ListStats};
+
+mod senders;
+pub use senders::{SenderReport, SenderStats};
+
+mod sizes;
+pub use sizes::{LargeMessage, SizeReport, SizeTotals};
+
+mod table;
+pub use table::ReportFormat;
+pub(crate) use table::{write_totals, Table};
+
+/// An analysis of fetched records, displaying the report prints the results as text or, in the
+/// alternate form, as tab separated values, see ReportFormat
+pub trait Report: Display {
+    /// The results of several reports combined, eg. of all users of a server
+    type Rollup: Display;
+
+    /// The fields a record needs to contain to be accounted for in the report
+    fn required_fields(&self) -> Vec<ImapField>;
+
+    fn add_record(&mut self, record: &FetchRecord);
+
+    /// An empty roll up for reports like this one
+    fn new_rollup(&self) -> Self::Rollup;
+
+    /// Add the results of the report to rollup. A roll up keeps aggregates only, reports that list
+    /// individual messages contribute their totals.
+    fn roll_up(&self, rollup: &mut Self::Rollup);
+}
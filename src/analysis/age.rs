@@ -0,0 +1,157 @@
+use crate::analysis::{write_totals, Report, Table};
+use crate::doveadm::{FetchRecord, ImapField};
+use chrono::{DateTime, FixedOffset};
+use std::fmt::{Display, Formatter};
+
+const FLAG_SEEN: &str = "\\Seen";
+
+// the upper bounds of the age classes in days, messages at least as old as the last bound fall in
+// the last class
+const AGE_CLASSES: [(i64, &str); 6] = [
+    (30, "< 1 month"),
+    (182, "< 6 months"),
+    (365, "< 1 year"),
+    (730, "< 2 years"),
+    (1826, "< 5 years"),
+    (i64::MAX, ">= 5 years"),
+];
+const UNKNOWN_AGE: &str = "unknown";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AgeTotals {
+    pub count: usize,
+    pub unseen: usize,
+    pub size: u64,
+}
+
+impl AgeTotals {
+    fn add(&mut self, size: u64, seen: bool) {
+        self.count += 1;
+        self.size += size;
+        if !seen {
+            self.unseen += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &AgeTotals) {
+        self.count += other.count;
+        self.unseen += other.unseen;
+        self.size += other.size;
+    }
+}
+
+/// The distribution of messages over age classes by the date they were received, to size what
+/// an archiving or expiry policy would affect
+#[derive(Debug)]
+pub struct AgeReport {
+    now: DateTime<FixedOffset>,
+    classes: [AgeTotals; AGE_CLASSES.len()],
+    unknown: AgeTotals,
+    oldest: Option<DateTime<FixedOffset>>,
+}
+
+impl AgeReport {
+    /// Ages are computed relative to now
+    pub fn new(now: DateTime<FixedOffset>) -> AgeReport {
+        AgeReport {
+            now,
+            classes: [AgeTotals::default(); AGE_CLASSES.len()],
+            unknown: AgeTotals::default(),
+            oldest: None,
+        }
+    }
+
+    /// The totals of every age class, from the newest messages to the oldest, followed by the
+    /// messages without a date
+    pub fn classes(&self) -> Vec<(&'static str, AgeTotals)> {
+        AGE_CLASSES
+            .iter()
+            .map(|(_, name)| *name)
+            .zip(self.classes.iter().copied())
+            .chain(std::iter::once((UNKNOWN_AGE, self.unknown)))
+            .collect()
+    }
+
+    /// The date the oldest message was received
+    pub fn oldest(&self) -> Option<DateTime<FixedOffset>> {
+        self.oldest
+    }
+}
+
+impl Report for AgeReport {
+    type Rollup = AgeReport;
+
+    fn required_fields(&self) -> Vec<ImapField> {
+        vec![
+            ImapField::Flags,
+            ImapField::DateReceived,
+            ImapField::SizePhysical,
+        ]
+    }
+
+    fn add_record(&mut self, record: &FetchRecord) {
+        let size = record.size_physical().unwrap_or(0);
+        let seen = record.has_flag(FLAG_SEEN);
+        let date = match record.date_received() {
+            Some(date) => *date,
+            None => {
+                self.unknown.add(size, seen);
+                return;
+            }
+        };
+        if self.oldest.is_none_or(|oldest| date < oldest) {
+            self.oldest = Some(date);
+        }
+        // messages from the future are counted as new
+        let days = (self.now - date).num_days();
+        let class = AGE_CLASSES
+            .iter()
+            .position(|(bound, _)| days < *bound)
+            .unwrap_or(AGE_CLASSES.len() - 1);
+        self.classes[class].add(size, seen);
+    }
+
+    fn new_rollup(&self) -> AgeReport {
+        AgeReport::new(self.now)
+    }
+
+    fn roll_up(&self, rollup: &mut AgeReport) {
+        for (totals, other) in rollup.classes.iter_mut().zip(self.classes.iter()) {
+            totals.merge(other);
+        }
+        rollup.unknown.merge(&self.unknown);
+        if let Some(oldest) = self.oldest {
+            if rollup.oldest.is_none_or(|date| oldest < date) {
+                rollup.oldest = Some(oldest);
+            }
+        }
+    }
+}
+
+impl Display for AgeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut table = Table::new()
+            .left("age", 12)
+            .right("count", 8)
+            .right("unseen", 8)
+            .right("size", 14);
+        for (name, totals) in self.classes() {
+            table.row(vec![
+                name.to_owned(),
+                totals.count.to_string(),
+                totals.unseen.to_string(),
+                totals.size.to_string(),
+            ]);
+        }
+        table.write(f)?;
+        if let Some(oldest) = self.oldest {
+            let oldest = oldest.format("%Y-%m-%d").to_string();
+            write_totals(
+                f,
+                format_args!("oldest message received {}", oldest),
+                &[("oldest", oldest.clone())],
+            )?;
+        }
+        Ok(())
+    }
+}
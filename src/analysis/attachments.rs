@@ -0,0 +1,319 @@
+use crate::analysis::{write_totals, Report, Table};
+use crate::doveadm::{FetchRecord, ImapField};
+use chrono::{DateTime, Datelike, FixedOffset};
+use log::debug;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+const UNKNOWN_SENDER: &str = "<unknown>";
+const UNKNOWN: &str = "-";
+
+/// An attachment found in a message's bodystructure
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub user: String,
+    pub mailbox: String,
+    pub uid: u64,
+    /// The date the message was received
+    pub date: Option<DateTime<FixedOffset>>,
+    /// The normalized address of the first From mailbox
+    pub sender: String,
+    pub filename: Option<String>,
+    pub mime_type: String,
+    /// The size as stored, in the transfer encoding
+    pub size: u64,
+    /// The approximate size after decoding the transfer encoding
+    pub decoded_size: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AttachmentTotals {
+    pub count: usize,
+    pub size: u64,
+    pub decoded_size: u64,
+}
+
+impl AttachmentTotals {
+    fn add(&mut self, attachment: &Attachment) {
+        self.count += 1;
+        self.size += attachment.size;
+        self.decoded_size += attachment.decoded_size;
+    }
+
+    fn merge(&mut self, other: &AttachmentTotals) {
+        self.count += other.count;
+        self.size += other.size;
+        self.decoded_size += other.decoded_size;
+    }
+}
+
+/// The attachment totals by MIME type, sender and year without the individual attachments, the
+/// roll up of AttachmentReport
+#[derive(Debug, Default)]
+pub struct AttachmentSummary {
+    attachments: usize,
+    messages: usize,
+    message_size: u64,
+    by_type: HashMap<String, AttachmentTotals>,
+    by_sender: HashMap<String, AttachmentTotals>,
+    by_year: HashMap<String, AttachmentTotals>,
+}
+
+impl AttachmentSummary {
+    /// Totals by MIME type, largest first
+    pub fn by_type(&self) -> Vec<(&String, &AttachmentTotals)> {
+        sorted_by_size(&self.by_type)
+    }
+
+    /// Totals by sender, largest first
+    pub fn by_sender(&self) -> Vec<(&String, &AttachmentTotals)> {
+        sorted_by_size(&self.by_sender)
+    }
+
+    /// Totals by the year the message was received, in ascending order
+    pub fn by_year(&self) -> Vec<(&String, &AttachmentTotals)> {
+        let mut res: Vec<(&String, &AttachmentTotals)> = self.by_year.iter().collect();
+        res.sort_by_key(|(year, _)| *year);
+        res
+    }
+
+    /// The number of messages with attachments and their total physical size
+    pub fn messages(&self) -> (usize, u64) {
+        (self.messages, self.message_size)
+    }
+
+    /// The number of attachments
+    pub fn attachments(&self) -> usize {
+        self.attachments
+    }
+
+    fn add(&mut self, attachment: &Attachment) {
+        let year = attachment
+            .date
+            .map_or_else(|| UNKNOWN.to_owned(), |date| date.year().to_string());
+        self.attachments += 1;
+        self.by_type
+            .entry(attachment.mime_type.clone())
+            .or_default()
+            .add(attachment);
+        self.by_sender
+            .entry(attachment.sender.clone())
+            .or_default()
+            .add(attachment);
+        self.by_year.entry(year).or_default().add(attachment);
+    }
+
+    fn merge(&mut self, other: &AttachmentSummary) {
+        self.attachments += other.attachments;
+        self.messages += other.messages;
+        self.message_size += other.message_size;
+        for (totals, other_totals) in [
+            (&mut self.by_type, &other.by_type),
+            (&mut self.by_sender, &other.by_sender),
+            (&mut self.by_year, &other.by_year),
+        ] {
+            for (key, other_totals) in other_totals {
+                totals.entry(key.clone()).or_default().merge(other_totals);
+            }
+        }
+    }
+}
+
+impl Display for AttachmentSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_breakdown(f, "type", &self.by_type())?;
+        write_breakdown(f, "sender", &self.by_sender())?;
+        write_breakdown(f, "year", &self.by_year())?;
+
+        write_totals(
+            f,
+            format_args!(
+                "{} attachments in {} messages of {} bytes",
+                self.attachments, self.messages, self.message_size
+            ),
+            &[
+                ("attachments", self.attachments.to_string()),
+                ("messages", self.messages.to_string()),
+                ("size", self.message_size.to_string()),
+            ],
+        )
+    }
+}
+
+/// Lists the attachments of the fetched messages and aggregates them by MIME type, sender and
+/// year. The structure is taken from imap.bodystructure, so message bodies are not fetched.
+#[derive(Debug, Default)]
+pub struct AttachmentReport {
+    min_size: u64,
+    attachments: Vec<Attachment>,
+    summary: AttachmentSummary,
+}
+
+impl AttachmentReport {
+    /// Attachments with an encoded size below min_size are ignored
+    pub fn new(min_size: u64) -> AttachmentReport {
+        AttachmentReport {
+            min_size,
+            ..AttachmentReport::default()
+        }
+    }
+
+    /// The attachments found, ordered by user, mailbox and UID
+    pub fn attachments(&self) -> Vec<&Attachment> {
+        let mut res: Vec<&Attachment> = self.attachments.iter().collect();
+        res.sort_by(|att1, att2| {
+            att1.user
+                .cmp(&att2.user)
+                .then(att1.mailbox.cmp(&att2.mailbox))
+                .then(att1.uid.cmp(&att2.uid))
+        });
+        res
+    }
+
+    /// Totals by MIME type, largest first
+    pub fn by_type(&self) -> Vec<(&String, &AttachmentTotals)> {
+        self.summary.by_type()
+    }
+
+    /// Totals by sender, largest first
+    pub fn by_sender(&self) -> Vec<(&String, &AttachmentTotals)> {
+        self.summary.by_sender()
+    }
+
+    /// Totals by the year the message was received, in ascending order
+    pub fn by_year(&self) -> Vec<(&String, &AttachmentTotals)> {
+        self.summary.by_year()
+    }
+
+    /// The number of messages with attachments and their total physical size
+    pub fn messages(&self) -> (usize, u64) {
+        self.summary.messages()
+    }
+}
+
+impl Report for AttachmentReport {
+    type Rollup = AttachmentSummary;
+
+    fn required_fields(&self) -> Vec<ImapField> {
+        vec![
+            ImapField::User,
+            ImapField::Mailbox,
+            ImapField::Uid,
+            ImapField::DateReceived,
+            ImapField::SizePhysical,
+            ImapField::ImapEnvelope,
+            ImapField::ImapBodystructure,
+        ]
+    }
+
+    fn add_record(&mut self, record: &FetchRecord) {
+        let body = match record.bodystructure() {
+            Some(body) => body,
+            None => return,
+        };
+        let sender = record
+            .envelope()
+            .and_then(|envelope| envelope.from_mailbox())
+            .map(|mailbox| mailbox.normalized())
+            .unwrap_or_else(|| UNKNOWN_SENDER.to_owned());
+
+        let mut found = false;
+        for part in body
+            .leaves()
+            .into_iter()
+            .filter(|part| part.is_attachment() && part.size >= self.min_size)
+        {
+            let attachment = Attachment {
+                user: record.user().unwrap_or(UNKNOWN).to_owned(),
+                mailbox: record.mailbox().unwrap_or(UNKNOWN).to_owned(),
+                uid: record.uid().unwrap_or(0),
+                date: record.date_received().copied(),
+                sender: sender.clone(),
+                filename: part.filename().map(|name| name.to_owned()),
+                mime_type: part.content_type.value.clone(),
+                size: part.size,
+                decoded_size: part.decoded_size(),
+            };
+            debug!("AttachmentReport::add_record: {:?}", attachment);
+            self.summary.add(&attachment);
+            self.attachments.push(attachment);
+            found = true;
+        }
+        if found {
+            self.summary.messages += 1;
+            self.summary.message_size += record.size_physical().unwrap_or(0);
+        }
+    }
+
+    fn new_rollup(&self) -> AttachmentSummary {
+        AttachmentSummary::default()
+    }
+
+    fn roll_up(&self, rollup: &mut AttachmentSummary) {
+        rollup.merge(&self.summary);
+    }
+}
+
+fn sorted_by_size(totals: &HashMap<String, AttachmentTotals>) -> Vec<(&String, &AttachmentTotals)> {
+    let mut res: Vec<(&String, &AttachmentTotals)> = totals.iter().collect();
+    res.sort_by(|(key1, totals1), (key2, totals2)| {
+        totals2.size.cmp(&totals1.size).then(key1.cmp(key2))
+    });
+    res
+}
+
+// a table of attachment totals, preceded by an empty line
+fn write_breakdown(
+    f: &mut Formatter<'_>,
+    title: &'static str,
+    totals: &[(&String, &AttachmentTotals)],
+) -> std::fmt::Result {
+    let mut table = Table::new()
+        .left(title, 0)
+        .right("count", 8)
+        .right("size", 12)
+        .right("decoded", 12);
+    for (key, totals) in totals {
+        table.row(vec![
+            key.to_string(),
+            totals.count.to_string(),
+            totals.size.to_string(),
+            totals.decoded_size.to_string(),
+        ]);
+    }
+    writeln!(f)?;
+    table.write(f)
+}
+
+impl Display for AttachmentReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut table = Table::new()
+            .left("user", 0)
+            .left("mailbox", 0)
+            .right("uid", 8)
+            .left("date", 10)
+            .left("sender", 0)
+            .right("size", 12)
+            .right("decoded", 12)
+            .left("type", 24)
+            .left("filename", 0);
+        for att in self.attachments() {
+            table.row(vec![
+                att.user.clone(),
+                att.mailbox.clone(),
+                att.uid.to_string(),
+                att.date.map_or_else(
+                    || UNKNOWN.to_owned(),
+                    |date| date.format("%Y-%m-%d").to_string(),
+                ),
+                att.sender.clone(),
+                att.size.to_string(),
+                att.decoded_size.to_string(),
+                att.mime_type.clone(),
+                att.filename.as_deref().unwrap_or(UNKNOWN).to_owned(),
+            ]);
+        }
+        table.write(f)?;
+        Display::fmt(&self.summary, f)
+    }
+}
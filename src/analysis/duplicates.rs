@@ -0,0 +1,349 @@
+use crate::analysis::{write_totals, Report, Table};
+use crate::doveadm::{FetchRecord, ImapField};
+use chrono::{DateTime, FixedOffset};
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+const HDR_MESSAGE_ID: &str = "message-id";
+// headers that are identical in all copies of a message, unlike eg. Received. The Message-ID is
+// part of the key in its normalized form.
+const HASHED_HEADERS: &[&str] = &["date", "from", "to", "cc", "subject"];
+const UNKNOWN: &str = "-";
+
+/// A copy of a message that has duplicates
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateMessage {
+    pub user: String,
+    pub mailbox: String,
+    pub mailbox_guid: String,
+    pub uid: u64,
+    pub guid: String,
+    pub size: u64,
+    pub date_received: Option<DateTime<FixedOffset>>,
+    pub flags: Vec<String>,
+}
+
+/// Messages of a user with the same normalized Message-ID and, if enabled, the same content hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateSet {
+    pub user: String,
+    pub message_id: Option<String>,
+    /// The hex encoded SHA-256 of the hashed headers and the body
+    pub hash: Option<String>,
+    /// The copies ordered by mailbox and UID
+    pub messages: Vec<DuplicateMessage>,
+}
+
+impl DuplicateSet {
+    pub fn size(&self) -> u64 {
+        self.messages.iter().map(|msg| msg.size).sum()
+    }
+
+    /// The bytes freed by keeping only one copy, assuming the largest one is kept
+    pub fn reclaimable(&self) -> u64 {
+        self.size() - self.messages.iter().map(|msg| msg.size).max().unwrap_or(0)
+    }
+}
+
+/// The duplicate totals without the individual sets, the roll up of DuplicateReport
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DuplicateSummary {
+    pub sets: usize,
+    pub messages: usize,
+    /// The bytes freed by removing all duplicates
+    pub reclaimable: u64,
+    /// The number of messages that could not be grouped
+    pub skipped: usize,
+}
+
+impl DuplicateSummary {
+    fn merge(&mut self, other: &DuplicateSummary) {
+        self.sets += other.sets;
+        self.messages += other.messages;
+        self.reclaimable += other.reclaimable;
+        self.skipped += other.skipped;
+    }
+}
+
+impl Display for DuplicateSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_totals(
+            f,
+            format_args!(
+                "{} duplicate sets, {} messages, {} bytes reclaimable",
+                self.sets, self.messages, self.reclaimable
+            ),
+            &[
+                ("sets", self.sets.to_string()),
+                ("messages", self.messages.to_string()),
+                ("reclaimable", self.reclaimable.to_string()),
+                ("skipped", self.skipped.to_string()),
+            ],
+        )?;
+        if self.skipped > 0 && !f.alternate() {
+            writeln!(f, "{} messages without Message-ID ignored", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Hash)]
+struct DuplicateKey {
+    user: String,
+    message_id: Option<String>,
+    hash: Option<String>,
+}
+
+/// Finds duplicate messages across the mailboxes of a user. Messages are grouped by user and
+/// normalized Message-ID, with content hashing enabled also by a hash of selected headers and the
+/// body, so messages that reuse a Message-ID with different content are not reported and messages
+/// without a Message-ID are still found.
+#[derive(Debug, Default)]
+pub struct DuplicateReport {
+    content_hash: bool,
+    messages: HashMap<DuplicateKey, Vec<DuplicateMessage>>,
+    skipped: usize,
+}
+
+impl DuplicateReport {
+    /// With content_hash the message bodies are fetched and hashed
+    pub fn new(content_hash: bool) -> DuplicateReport {
+        DuplicateReport {
+            content_hash,
+            ..DuplicateReport::default()
+        }
+    }
+
+    /// The sets of messages with more than one copy, most reclaimable bytes first
+    pub fn duplicates(&self) -> Vec<DuplicateSet> {
+        let mut res: Vec<DuplicateSet> = self
+            .messages
+            .iter()
+            .filter(|(_, messages)| messages.len() > 1)
+            .map(|(key, messages)| {
+                let mut messages = messages.clone();
+                messages.sort_by(|msg1, msg2| {
+                    msg1.mailbox
+                        .cmp(&msg2.mailbox)
+                        .then(msg1.uid.cmp(&msg2.uid))
+                });
+                DuplicateSet {
+                    user: key.user.clone(),
+                    message_id: key.message_id.clone(),
+                    hash: key.hash.clone(),
+                    messages,
+                }
+            })
+            .collect();
+        res.sort_by(|set1, set2| {
+            set2.reclaimable()
+                .cmp(&set1.reclaimable())
+                .then_with(|| set1.user.cmp(&set2.user))
+                .then_with(|| set1.message_id.cmp(&set2.message_id))
+                .then_with(|| set1.hash.cmp(&set2.hash))
+        });
+        res
+    }
+
+    /// The bytes freed by removing all duplicates
+    pub fn reclaimable(&self) -> u64 {
+        self.duplicates()
+            .iter()
+            .map(DuplicateSet::reclaimable)
+            .sum()
+    }
+
+    /// The number of messages that could not be grouped, having neither a Message-ID nor a hash
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    pub fn summary(&self) -> DuplicateSummary {
+        summarize(&self.duplicates(), self.skipped)
+    }
+}
+
+fn summarize(duplicates: &[DuplicateSet], skipped: usize) -> DuplicateSummary {
+    DuplicateSummary {
+        sets: duplicates.len(),
+        messages: duplicates.iter().map(|set| set.messages.len()).sum(),
+        reclaimable: duplicates.iter().map(DuplicateSet::reclaimable).sum(),
+        skipped,
+    }
+}
+
+impl Report for DuplicateReport {
+    type Rollup = DuplicateSummary;
+
+    fn required_fields(&self) -> Vec<ImapField> {
+        let mut fields = vec![
+            ImapField::User,
+            ImapField::Mailbox,
+            ImapField::MailboxGuid,
+            ImapField::Uid,
+            ImapField::Guid,
+            ImapField::SizePhysical,
+            ImapField::DateReceived,
+            ImapField::Flags,
+            ImapField::Hdr,
+        ];
+        if self.content_hash {
+            fields.push(ImapField::Body);
+        }
+        fields
+    }
+
+    fn add_record(&mut self, record: &FetchRecord) {
+        let user = record.user().unwrap_or(UNKNOWN);
+        let key = DuplicateKey {
+            user: user.to_owned(),
+            message_id: record.header(HDR_MESSAGE_ID).and_then(normalize_message_id),
+            hash: if self.content_hash {
+                Some(content_hash(record))
+            } else {
+                None
+            },
+        };
+        if key.message_id.is_none() && key.hash.is_none() {
+            debug!("DuplicateReport::add_record: no Message-ID in {:?}", record);
+            self.skipped += 1;
+            return;
+        }
+        let message = DuplicateMessage {
+            user: user.to_owned(),
+            mailbox: record.mailbox().unwrap_or(UNKNOWN).to_owned(),
+            mailbox_guid: record.mailbox_guid().unwrap_or(UNKNOWN).to_owned(),
+            uid: record.uid().unwrap_or(0),
+            guid: record.guid().unwrap_or(UNKNOWN).to_owned(),
+            size: record.size_physical().unwrap_or(0),
+            date_received: record.date_received().copied(),
+            flags: record.flags().unwrap_or_default().to_vec(),
+        };
+        debug!("DuplicateReport::add_record: {:?} {:?}", key, message);
+        self.messages.entry(key).or_default().push(message);
+    }
+
+    fn new_rollup(&self) -> DuplicateSummary {
+        DuplicateSummary::default()
+    }
+
+    fn roll_up(&self, rollup: &mut DuplicateSummary) {
+        rollup.merge(&self.summary());
+    }
+}
+
+impl Display for DuplicateReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let duplicates = self.duplicates();
+        if f.alternate() {
+            // a row per message, the sets are told apart by user and id
+            let mut table = Table::new()
+                .left("user", 0)
+                .left("id", 0)
+                .left("mailbox", 0)
+                .right("uid", 0)
+                .right("size", 0)
+                .left("guid", 0);
+            for set in &duplicates {
+                let id = set
+                    .message_id
+                    .as_deref()
+                    .or(set.hash.as_deref())
+                    .unwrap_or(UNKNOWN);
+                for msg in &set.messages {
+                    table.row(vec![
+                        set.user.clone(),
+                        id.to_owned(),
+                        msg.mailbox.clone(),
+                        msg.uid.to_string(),
+                        msg.size.to_string(),
+                        msg.guid.clone(),
+                    ]);
+                }
+            }
+            table.write(f)?;
+            return Display::fmt(&summarize(&duplicates, self.skipped), f);
+        }
+        for set in &duplicates {
+            writeln!(
+                f,
+                "{}: {} ({} copies, {} bytes reclaimable)",
+                set.user,
+                set.message_id
+                    .as_deref()
+                    .or(set.hash.as_deref())
+                    .unwrap_or(UNKNOWN),
+                set.messages.len(),
+                set.reclaimable()
+            )?;
+            for msg in &set.messages {
+                writeln!(
+                    f,
+                    "    {:<32} {:>8} {:>12} {}",
+                    msg.mailbox, msg.uid, msg.size, msg.guid
+                )?;
+            }
+        }
+        Display::fmt(&summarize(&duplicates, self.skipped), f)
+    }
+}
+
+// the msg-id without angle brackets, comments or whitespace and with a lowercased domain part,
+// which is case insensitive unlike the local part
+fn normalize_message_id(value: &str) -> Option<String> {
+    let id = match (value.find('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value,
+    };
+    let id: String = id.split_whitespace().collect();
+    if id.is_empty() {
+        return None;
+    }
+    Some(match id.rfind('@') {
+        Some(at) => format!("{}@{}", &id[..at], id[at + 1..].to_lowercase()),
+        None => id,
+    })
+}
+
+fn content_hash(record: &FetchRecord) -> String {
+    let mut hasher = Sha256::new();
+    for name in HASHED_HEADERS {
+        for value in record.headers_all(name) {
+            hasher.update(name.as_bytes());
+            hasher.update(b": ");
+            hasher.update(value.trim().as_bytes());
+            hasher.update(b"\n");
+        }
+    }
+    hasher.update(b"\n");
+    hasher.update(record.content(&ImapField::Body).unwrap_or_default());
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_message_id() {
+        assert_eq!(
+            normalize_message_id(" <Abc.1@Example.COM> (comment)").as_deref(),
+            Some("Abc.1@example.com")
+        );
+        assert_eq!(
+            normalize_message_id("<abc@\n example.com>").as_deref(),
+            Some("abc@example.com")
+        );
+        assert_eq!(
+            normalize_message_id("no-brackets").as_deref(),
+            Some("no-brackets")
+        );
+        assert_eq!(normalize_message_id("<>"), None);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::analysis::{write_totals, Table};
+use crate::doveadm::MailboxInfo;
+use std::fmt::{Display, Formatter};
+
+/// Message counts and sizes per mailbox
+#[derive(Debug, Default)]
+pub struct FolderReport {
+    mailboxes: Vec<MailboxInfo>,
+}
+
+impl FolderReport {
+    pub fn new(mailboxes: Vec<MailboxInfo>) -> FolderReport {
+        FolderReport { mailboxes }
+    }
+
+    /// Add the counts and sizes of the mailboxes of other to those with the same name, to roll up
+    /// the mailboxes of several users. GUID, UID next and modseq are left empty.
+    pub fn merge(&mut self, other: &FolderReport) {
+        for mailbox in &other.mailboxes {
+            match self.mailboxes.iter_mut().find(|mb| mb.name == mailbox.name) {
+                Some(mb) => {
+                    mb.messages += mailbox.messages;
+                    mb.vsize += mailbox.vsize;
+                    mb.unseen += mailbox.unseen;
+                    mb.recent += mailbox.recent;
+                }
+                None => self.mailboxes.push(MailboxInfo {
+                    guid: String::new(),
+                    uid_next: 0,
+                    highest_modseq: 0,
+                    ..mailbox.clone()
+                }),
+            }
+        }
+    }
+
+    /// The mailboxes sorted by descending size, then by name
+    pub fn sorted(&self) -> Vec<&MailboxInfo> {
+        let mut res: Vec<&MailboxInfo> = self.mailboxes.iter().collect();
+        res.sort_by(|mb1, mb2| mb2.vsize.cmp(&mb1.vsize).then(mb1.name.cmp(&mb2.name)));
+        res
+    }
+}
+
+impl Display for FolderReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sorted = self.sorted();
+        let mut table = Table::new()
+            .left("mailbox", 0)
+            .right("messages", 8)
+            .right("unseen", 8)
+            .right("size", 14);
+        for mb in &sorted {
+            table.row(vec![
+                mb.name.clone(),
+                mb.messages.to_string(),
+                mb.unseen.to_string(),
+                mb.vsize.to_string(),
+            ]);
+        }
+        table.write(f)?;
+        let messages = sorted.iter().map(|mb| mb.messages).sum::<u64>();
+        let size = sorted.iter().map(|mb| mb.vsize).sum::<u64>();
+        write_totals(
+            f,
+            format_args!(
+                "{} mailboxes, {} messages of {} bytes",
+                sorted.len(),
+                messages,
+                size
+            ),
+            &[
+                ("mailboxes", sorted.len().to_string()),
+                ("messages", messages.to_string()),
+                ("size", size.to_string()),
+            ],
+        )
+    }
+}
@@ -0,0 +1,215 @@
+use crate::analysis::{write_totals, Report, Table};
+use crate::doveadm::{FetchRecord, ImapField};
+use chrono::{DateTime, FixedOffset};
+use log::debug;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+const HDR_LIST_ID: &str = "list-id";
+const FLAG_SEEN: &str = "\\Seen";
+
+#[derive(Debug, Default)]
+pub struct ListStats {
+    /// The description of the list from the List-Id header, eg. 'Rust Users' for
+    /// 'Rust Users <users.rust-lang.org>'
+    pub description: Option<String>,
+    pub count: usize,
+    pub unseen: usize,
+    pub size: u64,
+    pub last_seen: Option<DateTime<FixedOffset>>,
+}
+
+impl ListStats {
+    fn add(&mut self, size: u64, seen: bool, date: Option<DateTime<FixedOffset>>) {
+        self.count += 1;
+        self.size += size;
+        if !seen {
+            self.unseen += 1;
+        }
+        if let Some(date) = date {
+            if self.last_seen.is_none_or(|last| date > last) {
+                self.last_seen = Some(date);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &ListStats) {
+        if self.description.is_none() {
+            self.description = other.description.clone();
+        }
+        self.count += other.count;
+        self.unseen += other.unseen;
+        self.size += other.size;
+        if let Some(last) = other.last_seen {
+            if self.last_seen.is_none_or(|date| last > date) {
+                self.last_seen = Some(last);
+            }
+        }
+    }
+}
+
+/// Per mailing list statistics keyed by the id of the List-Id header (RFC 2919). Lists with many
+/// unseen messages are candidates for unsubscribing.
+#[derive(Debug, Default)]
+pub struct ListReport {
+    lists: HashMap<String, ListStats>,
+    others: usize,
+    others_size: u64,
+}
+
+impl ListReport {
+    pub fn new() -> ListReport {
+        ListReport::default()
+    }
+
+    /// Lists sorted by descending size, then by id
+    pub fn sorted(&self) -> Vec<(&String, &ListStats)> {
+        let mut res: Vec<(&String, &ListStats)> = self.lists.iter().collect();
+        res.sort_by(|(id1, stats1), (id2, stats2)| {
+            stats2.size.cmp(&stats1.size).then(id1.cmp(id2))
+        });
+        res
+    }
+
+    /// The number of messages not sent by a list and their total physical size
+    pub fn others(&self) -> (usize, u64) {
+        (self.others, self.others_size)
+    }
+}
+
+impl Report for ListReport {
+    type Rollup = ListReport;
+
+    fn required_fields(&self) -> Vec<ImapField> {
+        vec![
+            ImapField::Flags,
+            ImapField::DateReceived,
+            ImapField::SizePhysical,
+            ImapField::HdrField(HDR_LIST_ID.to_owned()),
+        ]
+    }
+
+    fn add_record(&mut self, record: &FetchRecord) {
+        let size = record.size_physical().unwrap_or(0);
+        let (id, description) = match record.header(HDR_LIST_ID).and_then(parse_list_id) {
+            Some(list_id) => list_id,
+            None => {
+                self.others += 1;
+                self.others_size += size;
+                return;
+            }
+        };
+        debug!(
+            "ListReport::add_record: list: {} description: {:?} size: {}",
+            id, description, size
+        );
+        let stats = self.lists.entry(id).or_default();
+        if stats.description.is_none() {
+            stats.description = description;
+        }
+        stats.add(
+            size,
+            record.has_flag(FLAG_SEEN),
+            record.date_received().copied(),
+        );
+    }
+
+    fn new_rollup(&self) -> ListReport {
+        ListReport::new()
+    }
+
+    fn roll_up(&self, rollup: &mut ListReport) {
+        for (id, stats) in &self.lists {
+            rollup.lists.entry(id.clone()).or_default().merge(stats);
+        }
+        rollup.others += self.others;
+        rollup.others_size += self.others_size;
+    }
+}
+
+impl Display for ListReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut table = Table::new()
+            .left("list", 0)
+            .right("count", 8)
+            .right("unseen", 8)
+            .right("size", 12)
+            .left("last", 10)
+            .left("description", 0);
+        for (id, stats) in self.sorted() {
+            table.row(vec![
+                id.clone(),
+                stats.count.to_string(),
+                stats.unseen.to_string(),
+                stats.size.to_string(),
+                stats.last_seen.map_or_else(
+                    || "-".to_owned(),
+                    |date| date.format("%Y-%m-%d").to_string(),
+                ),
+                stats.description.as_deref().unwrap_or("-").to_owned(),
+            ]);
+        }
+        table.write(f)?;
+        write_totals(
+            f,
+            format_args!(
+                "{} lists, {} messages of {} bytes not from lists",
+                self.lists.len(),
+                self.others,
+                self.others_size
+            ),
+            &[
+                ("lists", self.lists.len().to_string()),
+                ("other messages", self.others.to_string()),
+                ("other size", self.others_size.to_string()),
+            ],
+        )
+    }
+}
+
+// split a List-Id header into the lowercased list id and the description, the id is enclosed in
+// angle brackets but is taken as is if they are missing
+fn parse_list_id(value: &str) -> Option<(String, Option<String>)> {
+    let (description, id) = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => (&value[..start], &value[start + 1..end]),
+        _ => ("", value),
+    };
+    let id = id.trim();
+    if id.is_empty() {
+        return None;
+    }
+    let description = description.trim().trim_matches('"').trim();
+    Some((
+        id.to_lowercase(),
+        if description.is_empty() {
+            None
+        } else {
+            Some(description.to_owned())
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_id() {
+        assert_eq!(
+            parse_list_id("Rust Users <Users.Rust-Lang.org>"),
+            Some((
+                "users.rust-lang.org".to_owned(),
+                Some("Rust Users".to_owned())
+            ))
+        );
+        assert_eq!(
+            parse_list_id("\"Dev\" <dev.example.com>"),
+            Some(("dev.example.com".to_owned(), Some("Dev".to_owned())))
+        );
+        assert_eq!(
+            parse_list_id(" dev.example.com "),
+            Some(("dev.example.com".to_owned(), None))
+        );
+        assert_eq!(parse_list_id("Nothing <>"), None);
+    }
+}
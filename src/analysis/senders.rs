@@ -0,0 +1,167 @@
+use crate::analysis::{Report, Table};
+use crate::doveadm::{FetchRecord, ImapField};
+use crate::mail::{parse_date, Address, Mailbox};
+use chrono::{DateTime, FixedOffset};
+use log::debug;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+const HDR_FROM: &str = "from";
+const HDR_DATE: &str = "date";
+const UNKNOWN_SENDER: &str = "<unknown>";
+
+#[derive(Debug, Default)]
+pub struct SenderStats {
+    pub count: usize,
+    pub size: u64,
+    pub first_seen: Option<DateTime<FixedOffset>>,
+    pub last_seen: Option<DateTime<FixedOffset>>,
+}
+
+impl SenderStats {
+    fn add(&mut self, size: u64, date: Option<DateTime<FixedOffset>>) {
+        self.count += 1;
+        self.size += size;
+        if let Some(date) = date {
+            if self.first_seen.is_none_or(|first| date < first) {
+                self.first_seen = Some(date);
+            }
+            if self.last_seen.is_none_or(|last| date > last) {
+                self.last_seen = Some(date);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &SenderStats) {
+        self.count += other.count;
+        self.size += other.size;
+        if let Some(first) = other.first_seen {
+            if self.first_seen.is_none_or(|date| first < date) {
+                self.first_seen = Some(first);
+            }
+        }
+        if let Some(last) = other.last_seen {
+            if self.last_seen.is_none_or(|date| last > date) {
+                self.last_seen = Some(last);
+            }
+        }
+    }
+}
+
+/// Per sender statistics keyed by the normalized address of the From header
+#[derive(Debug, Default)]
+pub struct SenderReport {
+    senders: HashMap<String, SenderStats>,
+}
+
+impl SenderReport {
+    pub fn new() -> SenderReport {
+        SenderReport::default()
+    }
+
+    /// Senders sorted by descending message count, then by address
+    pub fn sorted(&self) -> Vec<(&String, &SenderStats)> {
+        let mut res: Vec<(&String, &SenderStats)> = self.senders.iter().collect();
+        res.sort_by(|(addr1, stats1), (addr2, stats2)| {
+            stats2.count.cmp(&stats1.count).then(addr1.cmp(addr2))
+        });
+        res
+    }
+}
+
+impl Report for SenderReport {
+    type Rollup = SenderReport;
+
+    fn required_fields(&self) -> Vec<ImapField> {
+        vec![ImapField::Hdr, ImapField::SizePhysical]
+    }
+
+    fn add_record(&mut self, record: &FetchRecord) {
+        let sender = match record.addresses(HDR_FROM) {
+            Ok(addresses) => addresses
+                .iter()
+                .flat_map(Address::mailboxes)
+                .next()
+                .map(Mailbox::normalized),
+            Err(err) => {
+                debug!("SenderReport::add_record: {:#}", err);
+                record.header(HDR_FROM).map(normalize_address)
+            }
+        };
+        let date = record.header(HDR_DATE).and_then(parse_date);
+        let size = record.size_physical().unwrap_or(0);
+
+        let sender = sender.unwrap_or_else(|| UNKNOWN_SENDER.to_owned());
+        debug!(
+            "SenderReport::add_record: sender: {} size: {} date: {:?}",
+            sender, size, date
+        );
+        self.senders.entry(sender).or_default().add(size, date);
+    }
+
+    fn new_rollup(&self) -> SenderReport {
+        SenderReport::new()
+    }
+
+    fn roll_up(&self, rollup: &mut SenderReport) {
+        for (sender, stats) in &self.senders {
+            rollup
+                .senders
+                .entry(sender.clone())
+                .or_default()
+                .merge(stats);
+        }
+    }
+}
+
+impl Display for SenderReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut table = Table::new()
+            .left("sender", 0)
+            .right("count", 8)
+            .right("size", 12)
+            .left("first", 10)
+            .left("last", 0);
+        for (addr, stats) in self.sorted() {
+            table.row(vec![
+                addr.clone(),
+                stats.count.to_string(),
+                stats.size.to_string(),
+                format_date(&stats.first_seen),
+                format_date(&stats.last_seen),
+            ]);
+        }
+        table.write(f)
+    }
+}
+
+fn format_date(date: &Option<DateTime<FixedOffset>>) -> String {
+    date.map_or_else(
+        || "-".to_owned(),
+        |date| date.format("%Y-%m-%d").to_string(),
+    )
+}
+
+// extract the addr-spec from a From header value that is not a valid address list and
+// lowercase it, 'Alice <Alice@Example.com' and 'alice@example.com' map to the same sender
+fn normalize_address(value: &str) -> String {
+    let addr = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value,
+    };
+    addr.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(
+            normalize_address("Alice <Alice@Example.com>"),
+            "alice@example.com"
+        );
+        assert_eq!(normalize_address(" bob@example.com "), "bob@example.com");
+    }
+}
@@ -0,0 +1,209 @@
+use crate::analysis::{write_totals, Report, Table};
+use crate::doveadm::{FetchRecord, ImapField};
+use chrono::{DateTime, FixedOffset};
+use log::debug;
+use std::fmt::{Display, Formatter};
+
+const HDR_SUBJECT: &str = "subject";
+const UNKNOWN: &str = "-";
+
+// the upper bounds of the size classes, messages of at least the last bound fall in the last class
+const SIZE_CLASSES: [(u64, &str); 5] = [
+    (10 * 1024, "< 10k"),
+    (100 * 1024, "< 100k"),
+    (1024 * 1024, "< 1M"),
+    (10 * 1024 * 1024, "< 10M"),
+    (u64::MAX, ">= 10M"),
+];
+
+/// A message among the largest ones
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LargeMessage {
+    pub user: String,
+    pub mailbox: String,
+    pub uid: u64,
+    pub date: Option<DateTime<FixedOffset>>,
+    pub size: u64,
+    pub subject: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeTotals {
+    pub count: usize,
+    pub size: u64,
+}
+
+/// The distribution of message sizes over size classes and the largest messages
+#[derive(Debug)]
+pub struct SizeReport {
+    top: usize,
+    largest: Vec<LargeMessage>,
+    classes: [SizeTotals; SIZE_CLASSES.len()],
+    totals: SizeTotals,
+}
+
+impl SizeReport {
+    /// Keep the top largest messages
+    pub fn new(top: usize) -> SizeReport {
+        SizeReport {
+            top,
+            largest: Vec::new(),
+            classes: [SizeTotals::default(); SIZE_CLASSES.len()],
+            totals: SizeTotals::default(),
+        }
+    }
+
+    /// The largest messages, largest first, ties are ordered by user, mailbox and UID
+    pub fn largest(&self) -> &[LargeMessage] {
+        &self.largest
+    }
+
+    /// The totals of every size class, from the smallest messages to the largest
+    pub fn classes(&self) -> Vec<(&'static str, SizeTotals)> {
+        SIZE_CLASSES
+            .iter()
+            .map(|(_, name)| *name)
+            .zip(self.classes.iter().copied())
+            .collect()
+    }
+
+    /// The number of messages and their total physical size
+    pub fn totals(&self) -> SizeTotals {
+        self.totals
+    }
+
+    // whether a message of size is among the top largest so far
+    fn is_large(&self, size: u64) -> bool {
+        self.top > 0
+            && (self.largest.len() < self.top
+                || self.largest.last().is_some_and(|msg| msg.size < size))
+    }
+
+    fn add_large(&mut self, message: LargeMessage) {
+        self.largest.push(message);
+        self.largest.sort_by(|msg1, msg2| {
+            msg2.size
+                .cmp(&msg1.size)
+                .then(msg1.user.cmp(&msg2.user))
+                .then(msg1.mailbox.cmp(&msg2.mailbox))
+                .then(msg1.uid.cmp(&msg2.uid))
+        });
+        self.largest.truncate(self.top);
+    }
+}
+
+impl Report for SizeReport {
+    type Rollup = SizeReport;
+
+    fn required_fields(&self) -> Vec<ImapField> {
+        vec![
+            ImapField::User,
+            ImapField::Mailbox,
+            ImapField::Uid,
+            ImapField::DateReceived,
+            ImapField::SizePhysical,
+            ImapField::HdrField(HDR_SUBJECT.to_owned()),
+        ]
+    }
+
+    fn add_record(&mut self, record: &FetchRecord) {
+        let size = match record.size_physical() {
+            Some(size) => size,
+            None => return,
+        };
+        self.totals.count += 1;
+        self.totals.size += size;
+        let class = SIZE_CLASSES
+            .iter()
+            .position(|(bound, _)| size < *bound)
+            .unwrap_or(SIZE_CLASSES.len() - 1);
+        self.classes[class].count += 1;
+        self.classes[class].size += size;
+
+        if !self.is_large(size) {
+            return;
+        }
+        let message = LargeMessage {
+            user: record.user().unwrap_or(UNKNOWN).to_owned(),
+            mailbox: record.mailbox().unwrap_or(UNKNOWN).to_owned(),
+            uid: record.uid().unwrap_or(0),
+            date: record.date_received().copied(),
+            size,
+            subject: record.header(HDR_SUBJECT).map(|subject| subject.to_owned()),
+        };
+        debug!("SizeReport::add_record: {:?}", message);
+        self.add_large(message);
+    }
+
+    fn new_rollup(&self) -> SizeReport {
+        SizeReport::new(self.top)
+    }
+
+    fn roll_up(&self, rollup: &mut SizeReport) {
+        for (totals, other) in rollup.classes.iter_mut().zip(self.classes.iter()) {
+            totals.count += other.count;
+            totals.size += other.size;
+        }
+        rollup.totals.count += self.totals.count;
+        rollup.totals.size += self.totals.size;
+        for message in &self.largest {
+            if rollup.is_large(message.size) {
+                rollup.add_large(message.clone());
+            }
+        }
+    }
+}
+
+impl Display for SizeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut classes = Table::new()
+            .left("size", 8)
+            .right("count", 8)
+            .right("total", 14);
+        for (name, totals) in self.classes() {
+            classes.row(vec![
+                name.to_owned(),
+                totals.count.to_string(),
+                totals.size.to_string(),
+            ]);
+        }
+        classes.write(f)?;
+
+        if !self.largest.is_empty() {
+            let mut largest = Table::new()
+                .left("user", 0)
+                .left("mailbox", 0)
+                .right("uid", 8)
+                .left("date", 10)
+                .right("size", 12)
+                .left("subject", 0);
+            for msg in &self.largest {
+                largest.row(vec![
+                    msg.user.clone(),
+                    msg.mailbox.clone(),
+                    msg.uid.to_string(),
+                    msg.date.map_or_else(
+                        || UNKNOWN.to_owned(),
+                        |date| date.format("%Y-%m-%d").to_string(),
+                    ),
+                    msg.size.to_string(),
+                    msg.subject.as_deref().unwrap_or(UNKNOWN).to_owned(),
+                ]);
+            }
+            writeln!(f)?;
+            largest.write(f)?;
+        }
+
+        write_totals(
+            f,
+            format_args!(
+                "{} messages of {} bytes",
+                self.totals.count, self.totals.size
+            ),
+            &[
+                ("messages", self.totals.count.to_string()),
+                ("size", self.totals.size.to_string()),
+            ],
+        )
+    }
+}
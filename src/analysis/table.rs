@@ -0,0 +1,203 @@
+use anyhow::{anyhow, Error, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The layouts reports are printed in. Reports print text by default and tab separated values in
+/// the alternate form of Display, ie. with '{:#}'.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Aligned columns and totals in sentences, for people
+    #[default]
+    Text,
+    /// A line of column names and a line of tab separated values per row for every table, tables
+    /// separated by empty lines. Totals are tables of names and values.
+    Tsv,
+}
+
+impl ReportFormat {
+    /// Write report to f in this format
+    pub fn write(&self, f: &mut dyn std::io::Write, report: &dyn Display) -> std::io::Result<()> {
+        match self {
+            ReportFormat::Text => write!(f, "{}", report),
+            ReportFormat::Tsv => write!(f, "{:#}", report),
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "tsv" => Ok(ReportFormat::Tsv),
+            _ => Err(anyhow!("invalid report format {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+}
+
+// a table of a report. As text the columns are padded to the widest value or their minimum width,
+// a left aligned last column is not padded.
+pub(crate) struct Table {
+    columns: Vec<(&'static str, Align, usize)>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new() -> Table {
+        Table {
+            columns: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn left(mut self, name: &'static str, width: usize) -> Table {
+        self.columns.push((name, Align::Left, width));
+        self
+    }
+
+    pub fn right(mut self, name: &'static str, width: usize) -> Table {
+        self.columns.push((name, Align::Right, width));
+        self
+    }
+
+    // a row with a value per column
+    pub fn row(&mut self, values: Vec<String>) {
+        self.rows.push(values);
+    }
+
+    pub fn write(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            self.write_tsv(f)
+        } else {
+            self.write_text(f)
+        }
+    }
+
+    fn write_text(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, _, width))| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(idx))
+                    .map(|value| value.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(name.len())
+                    .max(*width)
+            })
+            .collect();
+        let names: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, ..)| name.to_string())
+            .collect();
+        for row in std::iter::once(&names).chain(self.rows.iter()) {
+            let mut line = String::new();
+            for (idx, ((_, align, _), value)) in self.columns.iter().zip(row).enumerate() {
+                if idx > 0 {
+                    line.push(' ');
+                }
+                match align {
+                    Align::Left if idx + 1 == self.columns.len() => line.push_str(value),
+                    Align::Left => {
+                        line.push_str(&format!("{:<width$}", value, width = widths[idx]))
+                    }
+                    Align::Right => {
+                        line.push_str(&format!("{:>width$}", value, width = widths[idx]))
+                    }
+                }
+            }
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    fn write_tsv(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.columns.iter().map(|(name, ..)| *name).collect();
+        writeln!(f, "{}", names.join("\t"))?;
+        for row in &self.rows {
+            let values: Vec<String> = row.iter().map(|value| tsv_value(value)).collect();
+            writeln!(f, "{}", values.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+// tabs and line breaks would break the row
+fn tsv_value(value: &str) -> String {
+    value.replace(['\t', '\r', '\n'], " ")
+}
+
+// the totals of a report, as text the sentence text, as tsv a table of the names and values
+pub(crate) fn write_totals(
+    f: &mut Formatter<'_>,
+    text: std::fmt::Arguments<'_>,
+    totals: &[(&'static str, String)],
+) -> std::fmt::Result {
+    if f.alternate() {
+        let mut table = Table::new().left("total", 0).left("value", 0);
+        for (name, value) in totals {
+            table.row(vec![name.to_string(), value.clone()]);
+        }
+        writeln!(f)?;
+        table.write(f)
+    } else {
+        writeln!(f, "\n{}", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Example(Table);
+
+    impl Display for Example {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            self.0.write(f)?;
+            write_totals(
+                f,
+                format_args!("{} rows", self.0.rows.len()),
+                &[("rows", self.0.rows.len().to_string())],
+            )
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        let mut table = Table::new()
+            .left("name", 0)
+            .right("count", 8)
+            .left("note", 0);
+        table.row(vec![
+            "alice@example.com".to_owned(),
+            "3".to_owned(),
+            "a\tb".to_owned(),
+        ]);
+        table.row(vec!["bob".to_owned(), "12".to_owned(), "-".to_owned()]);
+        let example = Example(table);
+        assert_eq!(
+            example.to_string(),
+            "name                 count note\n\
+             alice@example.com        3 a\tb\n\
+             bob                     12 -\n\
+             \n2 rows\n"
+        );
+        assert_eq!(
+            format!("{:#}", example),
+            "name\tcount\tnote\nalice@example.com\t3\ta b\nbob\t12\t-\n\ntotal\tvalue\nrows\t2\n"
+        );
+        assert_eq!("TSV".parse::<ReportFormat>().unwrap(), ReportFormat::Tsv);
+        assert!("json".parse::<ReportFormat>().is_err());
+    }
+}
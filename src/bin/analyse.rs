@@ -3,5 +3,5 @@ use mail_kraken::{fetch, CmdArgs};
 use structopt::StructOpt;
 
 fn main() -> Result<()> {
-    Ok(fetch(CmdArgs::from_args())?)
+    fetch(CmdArgs::from_args())
 }
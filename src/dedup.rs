@@ -0,0 +1,408 @@
+use crate::analysis::{DuplicateMessage, DuplicateSet};
+use crate::doveadm::{DoveadmCmd, DoveadmError, DoveadmErrorKind, SearchParam, SeqElement, SeqSet};
+use anyhow::{anyhow, Context, Error, Result};
+use log::{info, warn};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::str::FromStr;
+
+const UNKNOWN: &str = "-";
+
+/// Selects the copy of a duplicate set that is kept, ties are resolved by keeping the oldest
+/// copy, then by mailbox and UID
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// the copy received first
+    #[default]
+    Oldest,
+    /// a copy in the given mailbox
+    Folder(String),
+    /// the copy with the most flags, ie. the one that was answered, flagged etc.
+    MostFlags,
+}
+
+impl KeepPolicy {
+    pub fn select<'a>(&self, messages: &'a [DuplicateMessage]) -> Option<&'a DuplicateMessage> {
+        messages.iter().min_by(|msg1, msg2| {
+            let preference = match self {
+                KeepPolicy::Oldest => std::cmp::Ordering::Equal,
+                KeepPolicy::Folder(mailbox) => {
+                    (&msg2.mailbox == mailbox).cmp(&(&msg1.mailbox == mailbox))
+                }
+                KeepPolicy::MostFlags => msg2.flags.len().cmp(&msg1.flags.len()),
+            };
+            // messages without a date sort last
+            preference
+                .then_with(|| {
+                    (msg1.date_received.is_none(), msg1.date_received)
+                        .cmp(&(msg2.date_received.is_none(), msg2.date_received))
+                })
+                .then_with(|| msg1.mailbox.cmp(&msg2.mailbox))
+                .then_with(|| msg1.uid.cmp(&msg2.uid))
+        })
+    }
+}
+
+impl FromStr for KeepPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some((policy, mailbox)) if policy.eq_ignore_ascii_case("folder") => {
+                if mailbox.is_empty() {
+                    Err(anyhow!("missing mailbox in keep policy {}", s))
+                } else {
+                    Ok(KeepPolicy::Folder(mailbox.to_owned()))
+                }
+            }
+            _ => match s.to_lowercase().as_str() {
+                "oldest" => Ok(KeepPolicy::Oldest),
+                "most-flags" => Ok(KeepPolicy::MostFlags),
+                _ => Err(anyhow!("invalid keep policy {}", s)),
+            },
+        }
+    }
+}
+
+impl Display for KeepPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeepPolicy::Oldest => f.write_str("oldest"),
+            KeepPolicy::Folder(mailbox) => write!(f, "folder:{}", mailbox),
+            KeepPolicy::MostFlags => f.write_str("most-flags"),
+        }
+    }
+}
+
+/// What happens to the copies that are not kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemovalMethod {
+    /// move them to the given mailbox, which has to exist
+    Quarantine(String),
+    Expunge,
+}
+
+/// A copy to remove and the copy kept in its place
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Removal {
+    pub message: DuplicateMessage,
+    pub kept: DuplicateMessage,
+}
+
+/// Select the copy to keep in every set, all other copies are to be removed
+pub fn plan_removals(duplicates: &[DuplicateSet], policy: &KeepPolicy) -> Vec<Removal> {
+    let mut res = Vec::new();
+    for set in duplicates {
+        if let Some(kept) = policy.select(&set.messages) {
+            res.extend(
+                set.messages
+                    .iter()
+                    .filter(|msg| *msg != kept)
+                    .map(|msg| Removal {
+                        message: msg.clone(),
+                        kept: kept.clone(),
+                    }),
+            );
+        }
+    }
+    res
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RemovalSummary {
+    pub removed: usize,
+    pub size: u64,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+impl Display for RemovalSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} messages of {} bytes removed, {} failed, {} skipped",
+            self.removed, self.size, self.failed, self.skipped
+        )
+    }
+}
+
+/// Removes duplicate messages of a user with doveadm move or expunge. Messages are addressed by
+/// MAILBOX-GUID, UID and GUID so nothing but the fetched message can match.
+///
+/// Every removal is written to the action log as a tab separated line. For a message moved to
+/// quarantine the line holds the command that moves it back, expunged messages can not be
+/// restored. Removal is a dry run unless enabled with set_dry_run, in a dry run only the log is
+/// written.
+pub struct DuplicateRemover<W: Write> {
+    cmd: DoveadmCmd,
+    user: String,
+    method: RemovalMethod,
+    dry_run: bool,
+    log: W,
+}
+
+impl<W: Write> DuplicateRemover<W> {
+    pub fn new(user: String, method: RemovalMethod, log: W) -> DuplicateRemover<W> {
+        DuplicateRemover {
+            cmd: DoveadmCmd::new(),
+            user,
+            method,
+            dry_run: true,
+            log,
+        }
+    }
+
+    /// Remove messages with cmd instead of doveadm
+    pub fn set_cmd(&mut self, cmd: DoveadmCmd) -> &mut Self {
+        self.cmd = cmd;
+        self
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) -> &mut Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Consume the remover and return the action log
+    pub fn into_log(self) -> W {
+        self.log
+    }
+
+    /// Remove the messages, failures are logged and counted and the removal continues unless
+    /// doveadm fails for the user as a whole
+    pub fn remove(&mut self, removals: &[Removal]) -> Result<RemovalSummary> {
+        writeln!(
+            self.log,
+            "# duplicate removal for user {} at {}{}",
+            self.user,
+            chrono::Local::now().to_rfc3339(),
+            if self.dry_run { ", dry run" } else { "" }
+        )?;
+        writeln!(
+            self.log,
+            "# status\taction\tmailbox\tmailbox-guid\tuid\tguid\tsize\tkept\tundo"
+        )?;
+
+        let mut summary = RemovalSummary::default();
+        for removal in removals {
+            let msg = &removal.message;
+            let mut fatal = None;
+            let mut quarantined = None;
+            let status = if msg.mailbox_guid == UNKNOWN || msg.guid == UNKNOWN {
+                summary.skipped += 1;
+                "skipped: not addressable".to_owned()
+            } else if matches!(&self.method, RemovalMethod::Quarantine(mailbox) if *mailbox == msg.mailbox)
+            {
+                summary.skipped += 1;
+                "skipped: in quarantine".to_owned()
+            } else if self.dry_run {
+                summary.removed += 1;
+                summary.size += msg.size;
+                "dry-run".to_owned()
+            } else {
+                match self.remove_message(msg) {
+                    Ok(location) => {
+                        quarantined = location;
+                        summary.removed += 1;
+                        summary.size += msg.size;
+                        "ok".to_owned()
+                    }
+                    Err(err) => {
+                        warn!(
+                            "DuplicateRemover::remove: failed to remove {} {}: {:#}",
+                            msg.mailbox, msg.uid, err
+                        );
+                        summary.failed += 1;
+                        let status = format!("failed: {}", failure(&err));
+                        if is_fatal(&err) {
+                            fatal = Some(err);
+                        }
+                        status
+                    }
+                }
+            };
+            self.log_removal(removal, &status, quarantined)?;
+            if let Some(err) = fatal {
+                self.log.flush()?;
+                return Err(err);
+            }
+        }
+        self.log.flush()?;
+        info!("DuplicateRemover::remove: {}", summary);
+        Ok(summary)
+    }
+
+    // returns the mailbox GUID and UID of a message moved to quarantine if they could be found
+    fn remove_message(&self, msg: &DuplicateMessage) -> Result<Option<(String, u64)>> {
+        let search = [
+            SearchParam::MailboxGuid(msg.mailbox_guid.clone()),
+            SearchParam::Uid(SeqSet::new(SeqElement::Uid(msg.uid as usize))),
+            SearchParam::Guid(msg.guid.clone()),
+        ];
+        match &self.method {
+            RemovalMethod::Quarantine(mailbox) => {
+                self.cmd.move_mails(&self.user, mailbox, &search)?;
+                Ok(self.find_quarantined(mailbox, msg))
+            }
+            RemovalMethod::Expunge => self.cmd.expunge(&self.user, &search).map(|_| None),
+        }
+    }
+
+    // the message gets a new UID with the move. Copies of a message share its GUID, so the
+    // quarantine may hold several messages with it, the one just moved has the highest UID.
+    fn find_quarantined(&self, quarantine: &str, msg: &DuplicateMessage) -> Option<(String, u64)> {
+        let search = [
+            SearchParam::Mailbox(quarantine.to_owned()),
+            SearchParam::Guid(msg.guid.clone()),
+        ];
+        match self.cmd.search(&self.user, &search) {
+            Ok(found) => {
+                let res = found.into_iter().max_by_key(|(_, uid)| *uid);
+                if res.is_none() {
+                    warn!(
+                        "DuplicateRemover::find_quarantined: {} {} not found in {}",
+                        msg.mailbox, msg.uid, quarantine
+                    );
+                }
+                res
+            }
+            Err(err) => {
+                warn!(
+                    "DuplicateRemover::find_quarantined: failed to find {} {} in {}: {:#}",
+                    msg.mailbox, msg.uid, quarantine, err
+                );
+                None
+            }
+        }
+    }
+
+    fn log_removal(
+        &mut self,
+        removal: &Removal,
+        status: &str,
+        quarantined: Option<(String, u64)>,
+    ) -> Result<()> {
+        let msg = &removal.message;
+        let action = match &self.method {
+            RemovalMethod::Quarantine(mailbox) => format!("move:{}", mailbox),
+            RemovalMethod::Expunge => "expunge".to_owned(),
+        };
+        let undo = match quarantined {
+            Some((mailbox_guid, uid)) => [
+                "doveadm",
+                "move",
+                "-u",
+                &self.user,
+                &msg.mailbox,
+                "MAILBOX-GUID",
+                &mailbox_guid,
+                "UID",
+                &uid.to_string(),
+            ]
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<String>>()
+            .join(" "),
+            None => UNKNOWN.to_owned(),
+        };
+        writeln!(
+            self.log,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}:{}\t{}",
+            status,
+            action,
+            msg.mailbox,
+            msg.mailbox_guid,
+            msg.uid,
+            msg.guid,
+            msg.size,
+            removal.kept.mailbox,
+            removal.kept.uid,
+            undo
+        )
+        .with_context(|| "failed to write action log".to_owned())
+    }
+}
+
+fn doveadm_error_kind(err: &Error) -> Option<DoveadmErrorKind> {
+    err.downcast_ref::<DoveadmError>().map(|err| err.kind)
+}
+
+// errors that affect all messages of the user
+fn is_fatal(err: &Error) -> bool {
+    matches!(
+        doveadm_error_kind(err),
+        Some(DoveadmErrorKind::UnknownUser) | Some(DoveadmErrorKind::NoPermission)
+    )
+}
+
+fn failure(err: &Error) -> String {
+    match doveadm_error_kind(err) {
+        Some(kind) => kind.to_string(),
+        None => format!("{:#}", err).replace(['\t', '\n'], " "),
+    }
+}
+
+// quote arg for a POSIX shell if necessary
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "-_./@:+=,".contains(ch))
+    {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, FixedOffset};
+
+    fn message(mailbox: &str, uid: u64, date: Option<&str>, flags: &[&str]) -> DuplicateMessage {
+        DuplicateMessage {
+            user: "alice@example.com".to_owned(),
+            mailbox: mailbox.to_owned(),
+            mailbox_guid: format!("mb-{}", mailbox),
+            uid,
+            guid: format!("g{}", uid),
+            size: 100,
+            date_received: date
+                .map(|date| DateTime::<FixedOffset>::parse_from_rfc3339(date).unwrap()),
+            flags: flags.iter().map(|flag| flag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_keep_policy() {
+        let messages = vec![
+            message("INBOX", 1, None, &["\\Seen", "\\Flagged"]),
+            message("Archive", 2, Some("2022-08-15T10:00:00+02:00"), &[]),
+            message("Sent", 3, Some("2022-08-14T10:00:00+02:00"), &["\\Seen"]),
+            message("Archive", 4, Some("2022-08-16T10:00:00+02:00"), &[]),
+        ];
+        let kept = |policy: &str| {
+            policy
+                .parse::<KeepPolicy>()
+                .unwrap()
+                .select(&messages)
+                .unwrap()
+                .uid
+        };
+        assert_eq!(kept("oldest"), 3);
+        assert_eq!(kept("most-flags"), 1);
+        assert_eq!(kept("folder:Archive"), 2);
+        // no copy in the folder, the oldest is kept
+        assert_eq!(kept("folder:Trash"), 3);
+        assert!("folder:".parse::<KeepPolicy>().is_err());
+        assert!("newest".parse::<KeepPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("INBOX/a.b"), "INBOX/a.b");
+        assert_eq!(shell_quote("Sent Items"), "'Sent Items'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -1,53 +1,90 @@
-use crate::doveadm::parser::{FetchRecord, FlagsParser, GenericParser, HdrParser, Parser};
+use crate::doveadm::parser::{new_parser, Parser};
 use anyhow::{anyhow, Context, Result};
-use log::debug;
-use regex::Regex;
-use std::fmt::{format, Display, Formatter};
-use std::io::{BufRead, BufReader, Read, Stdin};
-use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
-use std::string::ToString;
-use strum_macros;
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, ChildStderr, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
 
 const MB_SIZE: usize = 1024 * 1024;
 const DOVEADM_CMD: &str = "doveadm";
 
-const LINE_FEED: char = 0xAu8 as char;
-const FORM_FEED: char = 0xCu8 as char;
+const LINE_FEED: u8 = 0xA;
+const FORM_FEED: u8 = 0xC;
+const TAB: u8 = 0x9;
 
 mod cmd_args;
-pub use cmd_args::CmdArgs;
+pub use cmd_args::{AnalyseCmd, CmdArgs, DuplicateArgs};
+
+mod command;
+pub use command::DoveadmCmd;
+
+mod error;
+pub use error::{DoveadmError, DoveadmErrorKind};
+
+mod mailbox_list;
+pub use mailbox_list::{DoveadmMailboxList, MailboxInfo};
 
 mod params;
-pub use params::{FetchParams, ImapField, SearchParam};
+pub use params::{
+    parse_query, parse_query_args, DateSpec, FetchParams, ImapField, OutputFormat, SearchParam,
+    SeqElement, SeqSet,
+};
 
 mod parser;
+pub use parser::{FetchFieldRes, FetchRecord, FieldType};
+
+mod recovery;
+pub use recovery::{Recovery, SkippedRecord};
+
+mod scheduler;
+pub use scheduler::{FetchEvent, FetchEvents, FetchProgress, FetchScheduler};
+
+mod sharded_fetch;
+pub use sharded_fetch::ShardedFetch;
+
+mod user_list;
+pub use user_list::DoveadmUserList;
 
 pub struct DoveadmFetch {
     params: FetchParams,
+    args: Vec<String>,
     child: Child,
-    stdout: BufReader<Box<dyn Read>>,
-    line_count: usize,
-    buffer: String,
+    reader: Reader,
+    stderr: Option<JoinHandle<Vec<String>>>,
+    stderr_lines: Vec<String>,
+    exit_status: Option<ExitStatus>,
     parsers: Vec<Box<dyn Parser>>,
+    format: OutputFormat,
+    recovery: Recovery,
+    skipped: Vec<SkippedRecord>,
+    finished: bool,
 }
 
 impl DoveadmFetch {
     pub fn new(params: FetchParams) -> Result<DoveadmFetch> {
+        DoveadmFetch::with_cmd(DOVEADM_CMD, params)
+    }
+
+    /// Run cmd instead of doveadm, cmd is expected to behave like 'doveadm fetch'
+    pub fn with_cmd<S: AsRef<OsStr>>(cmd: S, params: FetchParams) -> Result<DoveadmFetch> {
+        let args = params.to_args()?;
         debug!(
-            "DoveadmFetch::new: spawning command: {} params: {:?}",
-            DOVEADM_CMD,
-            params.to_args()
+            "DoveadmFetch::with_cmd: spawning command: {:?} params: {:?}",
+            cmd.as_ref(),
+            args
         );
-        let mut child = Command::new(DOVEADM_CMD)
-            .args(params.to_args()?)
+        let mut child = Command::new(cmd)
+            .args(&args)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit()) // TODO: do something with this ?
+            .stderr(Stdio::piped())
             .spawn()
             .with_context(|| "failed to spawn doveadm fetch command".to_owned())?;
 
-        let mut stdout = match child.stdout.take() {
-            Some(stdout) => BufReader::new(Box::new(stdout) as Box<dyn Read>),
+        let stdout = match child.stdout.take() {
+            Some(stdout) => Box::new(stdout) as Box<dyn Read>,
             None => {
                 return Err(anyhow!(
                     "unable to retrieve stdout handle for fetch command"
@@ -55,43 +92,153 @@ impl DoveadmFetch {
             }
         };
 
-        let mut parsers: Vec<Box<dyn Parser>> = Vec::new();
-        for field in params.fields() {
-            parsers.push(match field {
-                ImapField::Flags => Box::new(FlagsParser::new()?) as Box<dyn Parser>,
-                ImapField::Hdr => Box::new(HdrParser::new()?) as Box<dyn Parser>,
-                _ => Box::new(GenericParser::new(field)?) as Box<dyn Parser>,
-            });
-        }
+        let stderr = match child.stderr.take() {
+            Some(stderr) => stderr,
+            None => {
+                return Err(anyhow!(
+                    "unable to retrieve stderr handle for fetch command"
+                ))
+            }
+        };
+
+        let parsers = params
+            .fields()
+            .iter()
+            .map(new_parser)
+            .collect::<Result<Vec<Box<dyn Parser>>>>()?;
 
         Ok(DoveadmFetch {
+            format: params.output_format(),
             params,
+            args,
             child,
-            stdout,
-            line_count: 0,
-            buffer: String::new(),
+            reader: Reader::new(stdout),
+            stderr: Some(collect_stderr(stderr)),
+            stderr_lines: Vec::new(),
+            exit_status: None,
             parsers,
+            recovery: Recovery::default(),
+            skipped: Vec::new(),
+            finished: false,
         })
     }
 
+    pub fn params(&self) -> &FetchParams {
+        &self.params
+    }
+
+    /// Select what happens when a record can not be parsed, see Recovery
+    pub fn set_recovery(&mut self, recovery: Recovery) -> &mut Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// The records skipped so far in Recovery::Skip mode
+    pub fn skipped(&self) -> &[SkippedRecord] {
+        &self.skipped
+    }
+
     pub fn get_exit_status(&mut self) -> Result<ExitStatus> {
+        if let Some(exit_status) = self.exit_status {
+            return Ok(exit_status);
+        }
         self.flush_stdout()?;
-        self.child
+        let exit_status = self
+            .child
             .wait()
-            .with_context(|| "failed to wait for dveadm fetch to terminate".to_owned())
+            .with_context(|| "failed to wait for dveadm fetch to terminate".to_owned())?;
+        if let Some(stderr) = self.stderr.take() {
+            self.stderr_lines = stderr
+                .join()
+                .map_err(|_| anyhow!("doveadm fetch stderr reader panicked"))?;
+        }
+        self.exit_status = Some(exit_status);
+        Ok(exit_status)
     }
 
+    /// Wait for doveadm to terminate, fails with a DoveadmError if doveadm was not successful
+    pub fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        let exit_status = self.get_exit_status()?;
+        if exit_status.success() {
+            Ok(())
+        } else {
+            Err(
+                DoveadmError::new(&exit_status, self.stderr_lines.clone(), self.args.clone())
+                    .into(),
+            )
+        }
+    }
+
+    /// Parse the next record, returns Ok(None) at the end of output. Once the output is exhausted
+    /// or a parser error occurred, the exit status of doveadm is checked and reported as an error
+    /// if it was not successful.
+    ///
+    /// In Recovery::Skip mode malformed records are skipped and recorded in skipped instead, doveadm's
+    /// exit status is only checked at the end of output.
     pub fn parse_record(&mut self) -> Result<Option<FetchRecord>> {
-        FetchRecord::parse(
-            &self.parsers,
-            &mut Reader::new(&mut self.stdout, &mut self.buffer, &mut self.line_count),
-        )
+        loop {
+            if self.finished {
+                return Ok(None);
+            }
+            let first_line = self.reader.next_line_number();
+            let res = match self.format {
+                OutputFormat::Pager => FetchRecord::parse(&self.parsers, &mut self.reader),
+                OutputFormat::Tab => {
+                    FetchRecord::parse_tab(self.params.fields(), &self.parsers, &mut self.reader)
+                }
+                OutputFormat::Flow => {
+                    FetchRecord::parse_flow(self.params.fields(), &self.parsers, &mut self.reader)
+                }
+            };
+            match res {
+                Ok(Some(record)) => return Ok(Some(record)),
+                Ok(None) => {
+                    self.finish()?;
+                    return Ok(None);
+                }
+                Err(err) if self.recovery == Recovery::Skip && self.is_recoverable() => {
+                    let last_line = match self.reader.resync(
+                        first_line,
+                        self.parsers[0].get_first_line_re(),
+                        self.format,
+                    ) {
+                        Ok(last_line) => last_line,
+                        Err(err) => {
+                            // the reader is broken, do not read on
+                            self.finished = true;
+                            return Err(err);
+                        }
+                    };
+                    let skipped = SkippedRecord {
+                        first_line,
+                        last_line,
+                        reason: format!("{:#}", err),
+                    };
+                    warn!("DoveadmFetch::parse_record: skipped record, {}", skipped);
+                    self.skipped.push(skipped);
+                }
+                Err(err) => {
+                    // a failed doveadm is the more likely cause of a parser error, so report that
+                    // first
+                    debug!("DoveadmFetch::parse_record: parser error: {:?}", err);
+                    self.finish()?;
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    // the tab formatter's header line is not a record, a mismatch is fatal
+    fn is_recoverable(&self) -> bool {
+        self.format != OutputFormat::Tab || self.reader.line_count() > 1
     }
 
     fn flush_stdout(&mut self) -> Result<()> {
         let mut buf = vec![0u8; MB_SIZE];
         while self
-            .stdout
+            .reader
+            .stream
             .read(&mut buf[..])
             .with_context(|| "failed to read from doveadm fetch stdout")?
             > 0
@@ -100,67 +247,141 @@ impl DoveadmFetch {
     }
 }
 
+/// Iterates over the fetched records, the iteration ends after the last record or after the
+/// first error. The child process is reaped as soon as the iteration ends.
+impl Iterator for DoveadmFetch {
+    type Item = Result<FetchRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parse_record().transpose()
+    }
+}
+
 impl Drop for DoveadmFetch {
     fn drop(&mut self) {
-        // make sure stdout is flushed so process can terminate
-        let _ = self.get_exit_status();
+        // dropped before the end of output, kill doveadm instead of reading output nobody wants
+        if self.exit_status.is_none() {
+            debug!("DoveadmFetch::drop: killing unfinished doveadm fetch");
+            if let Err(err) = self.child.kill() {
+                warn!("DoveadmFetch::drop: failed to kill doveadm fetch: {}", err);
+            }
+            if let Err(err) = self.child.wait() {
+                warn!(
+                    "DoveadmFetch::drop: failed to wait for doveadm fetch: {}",
+                    err
+                );
+            }
+            if let Some(stderr) = self.stderr.take() {
+                let _ = stderr.join();
+            }
+        }
     }
 }
 
-pub struct Reader<'a> {
-    stream: &'a mut BufReader<Box<dyn Read>>,
-    buffer: &'a mut String,
-    line_count: &'a mut usize,
+// read stderr on a separate thread so doveadm can not block on a full stderr pipe
+fn collect_stderr(stderr: ChildStderr) -> JoinHandle<Vec<String>> {
+    thread::spawn(move || {
+        BufReader::new(stderr)
+            .lines()
+            .map_while(|line| line.ok())
+            .inspect(|line| debug!("doveadm stderr: {}", line))
+            .collect()
+    })
+}
+
+/// Reads doveadm output line by line. Lines are bytes as messages frequently contain 8-bit data
+/// that is not valid UTF-8, decoding is left to the field parsers.
+pub struct Reader {
+    stream: BufReader<Box<dyn Read>>,
+    buffer: Vec<u8>,
+    line_count: usize,
     consumed: bool,
 }
 
-impl<'a> Reader<'a> {
-    pub fn new(
-        stream: &'a mut BufReader<Box<dyn Read>>,
-        buffer: &'a mut String,
-        line_count: &'a mut usize,
-    ) -> Reader<'a> {
+impl Reader {
+    pub fn new(stream: Box<dyn Read>) -> Reader {
         Reader {
-            stream,
-            buffer,
-            line_count,
+            stream: BufReader::new(stream),
+            buffer: Vec::new(),
+            line_count: 0,
             consumed: true,
         }
     }
 
+    // push back the last line read, it will be returned again by the next call to next_line
     fn unconsume(&mut self) {
         self.consumed = false;
     }
 
-    fn next_line(&mut self) -> Result<Option<&str>> {
+    // the number of the line next_line returns next
+    fn next_line_number(&self) -> usize {
+        if self.consumed {
+            self.line_count + 1
+        } else {
+            self.line_count
+        }
+    }
+
+    // skip to the start of the next record after a parser error, returns the number of the last
+    // line skipped. Records of the tab and flow formatters are single lines, so for these the
+    // line that failed has already been skipped.
+    fn resync(
+        &mut self,
+        first_line: usize,
+        first_line_re: &regex::bytes::Regex,
+        format: OutputFormat,
+    ) -> Result<usize> {
+        if format != OutputFormat::Pager {
+            return Ok(self.line_count.max(first_line));
+        }
+        // the line that failed may already start the next record
+        if self.consumed
+            && self.line_count > first_line
+            && (self.buffer.ends_with(&[FORM_FEED]) || first_line_re.is_match(&self.buffer))
+        {
+            self.unconsume();
+            return Ok(self.line_count - 1);
+        }
+        while let Some(line) = self.next_line()? {
+            if line.ends_with(&[FORM_FEED]) || first_line_re.is_match(line) {
+                self.unconsume();
+                return Ok(self.line_count - 1);
+            }
+        }
+        Ok(self.line_count)
+    }
+
+    // returns the next line without its trailing line feed
+    fn next_line(&mut self) -> Result<Option<&[u8]>> {
         if !self.consumed {
-            Ok(Some(&self.buffer))
+            self.consumed = true;
+            Ok(Some(self.buffer.as_slice()))
         } else {
+            self.buffer.clear();
             if self
                 .stream
-                .read_line(&mut self.buffer)
+                .read_until(LINE_FEED, &mut self.buffer)
                 .with_context(|| "failed to read line from doveadm fetch stdout".to_owned())?
                 == 0
             {
                 Ok(None)
             } else {
-                *self.line_count += 1;
-                Ok(Some(self.buffer))
+                self.line_count += 1;
+                if self.buffer.ends_with(&[LINE_FEED]) {
+                    self.buffer.pop();
+                }
+                Ok(Some(self.buffer.as_slice()))
             }
         }
     }
 
-    #[allow(dead_code)]
-    fn expect_get_line(&mut self) -> Result<&str> {
-        if let Some(res) = self.next_line()? {
-            Ok(res)
-        } else {
-            Err(anyhow!("encountered unexpected EOI"))
-        }
+    // the number of lines read so far
+    fn line_count(&self) -> usize {
+        self.line_count
     }
 
-    #[allow(dead_code)]
-    fn line_count(&self) -> usize {
-        *self.line_count
+    // the last line read, for error messages
+    fn buffer_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.buffer)
     }
 }
@@ -1,21 +1,346 @@
-use crate::doveadm::ImapField;
+use crate::analysis::ReportFormat;
+use crate::dedup::KeepPolicy;
+use crate::doveadm::{ImapField, OutputFormat, Recovery, SearchParam};
 use mod_logger::Level;
+use std::path::PathBuf;
+use structopt::clap::ArgGroup;
 use structopt::StructOpt;
 
+// the analysis to run, with its own options
 #[derive(Debug, StructOpt)]
-#[structopt(name = "analyse", about = "analyse - analyse mailbox")]
+pub enum AnalyseCmd {
+    #[structopt(about = "print the fetched records")]
+    Fetch {
+        #[structopt(
+            short = "f",
+            long = "fields",
+            value_name = "FIELD",
+            required = true,
+            help = "the fields to fetch, eg. 'hdr.subject', may be given repeatedly"
+        )]
+        fields: Vec<ImapField>,
+    },
+
+    #[structopt(about = "messages per sender")]
+    Senders,
+
+    #[structopt(about = "message counts and sizes per mailbox")]
+    Folders,
+
+    #[structopt(about = "message size classes and the largest messages")]
+    Sizes {
+        #[structopt(
+            long,
+            value_name = "COUNT",
+            help = "the number of largest messages to list",
+            default_value = "20"
+        )]
+        top: usize,
+    },
+
+    #[structopt(about = "duplicate messages, optionally removing them")]
+    Duplicates(DuplicateArgs),
+
+    #[structopt(about = "messages per mailing list")]
+    Lists,
+
+    #[structopt(about = "attachment inventory")]
+    Attachments {
+        #[structopt(
+            long,
+            value_name = "BYTES",
+            help = "ignore attachments smaller than this",
+            default_value = "0"
+        )]
+        min_size: u64,
+    },
+
+    #[structopt(about = "messages by the time since they were received")]
+    Age,
+}
+
+// execute requires a removal method, quarantine or expunge
+#[derive(Debug, StructOpt)]
+#[structopt(group = ArgGroup::with_name("method"))]
+pub struct DuplicateArgs {
+    #[structopt(long, help = "also compare a hash of the message headers and body")]
+    pub content_hash: bool,
+
+    #[structopt(
+        long,
+        value_name = "POLICY",
+        help = "the copy to keep, one of (oldest, most-flags, folder:<mailbox>)",
+        default_value = "oldest"
+    )]
+    pub keep: KeepPolicy,
+
+    #[structopt(
+        long,
+        value_name = "MAILBOX",
+        group = "method",
+        help = "move duplicates to this existing mailbox"
+    )]
+    pub quarantine: Option<String>,
+
+    #[structopt(
+        long,
+        group = "method",
+        help = "expunge duplicates, expunged messages can not be restored, prefer --quarantine"
+    )]
+    pub expunge: bool,
+
+    #[structopt(
+        long,
+        requires = "method",
+        help = "remove duplicates as --quarantine or --expunge say, without this only the action log \
+                is written"
+    )]
+    pub execute: bool,
+
+    #[structopt(
+        long,
+        value_name = "FILE",
+        help = "the action log, duplicates-<user>-<time>.log by default",
+        parse(from_os_str)
+    )]
+    pub action_log: Option<PathBuf>,
+}
+
+/// The options of all commands, they may be given before or after the command
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "analyse",
+    about = "analyse - analyse mailbox",
+    group = ArgGroup::with_name("users")
+)]
 pub struct CmdArgs {
-    #[structopt(short, long, value_name = "USER", help = "fully email of a valid user")]
-    pub user: String,
+    #[structopt(subcommand)]
+    pub command: AnalyseCmd,
+
     #[structopt(
         short,
         long,
+        global = true,
+        value_name = "USER",
+        help = "fully email of a valid user",
+        group = "users"
+    )]
+    pub user: Option<String>,
+
+    #[structopt(
+        long,
+        global = true,
+        group = "users",
+        help = "analyse every user of the userdb"
+    )]
+    pub all_users: bool,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "MASK",
+        group = "users",
+        help = "analyse the users matching this mask, eg. '*@example.com'"
+    )]
+    pub user_mask: Option<String>,
+
+    #[structopt(
+        short,
+        long,
+        global = true,
         value_name = "LOGLEVEL",
         help = "Log Level, one of (error, warn, info, debug, trace)",
         default_value = "info"
     )]
     pub log_level: Level,
 
-    #[structopt(short = "f", long = "fields")]
-    pub fields: Vec<ImapField>,
+    #[structopt(
+        short,
+        long,
+        global = true,
+        value_name = "QUERY",
+        help = "doveadm search query, eg. 'mailbox INBOX since 2022-01-01 NOT deleted', \
+                'mailbox INBOX' if not given. Unseen messages are included, use 'mailbox INBOX \
+                seen' for the seen messages only as in earlier versions"
+    )]
+    pub query: Option<SearchParam>,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "fetch every mailbox of the user separately, restricted by the query if given"
+    )]
+    pub all_mailboxes: bool,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        help = "doveadm output format, one of (pager, tab, flow), chosen from the fields by default"
+    )]
+    pub format: Option<OutputFormat>,
+
+    #[structopt(
+        short,
+        long,
+        global = true,
+        value_name = "FORMAT",
+        help = "the layout of the reports, one of (text, tsv). tsv prints every table as a line \
+                of column names and a line of tab separated values per row",
+        default_value = "text"
+    )]
+    pub output: ReportFormat,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "MODE",
+        help = "what to do with records that can not be parsed, one of (abort, skip)",
+        default_value = "abort"
+    )]
+    pub recovery: Recovery,
+
+    #[structopt(
+        short,
+        long,
+        global = true,
+        value_name = "JOBS",
+        help = "the number of doveadm fetches to run concurrently",
+        default_value = "1"
+    )]
+    pub jobs: usize,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "JOBS",
+        help = "the number of doveadm fetches to run concurrently for a single user",
+        default_value = "1"
+    )]
+    pub jobs_per_user: usize,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "UIDS",
+        help = "fetch mailboxes in UID ranges of this size, 0 fetches every mailbox at once",
+        default_value = "0"
+    )]
+    pub shard_size: u64,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "RETRIES",
+        help = "the number of times a fetch that failed temporarily is retried, after 1s, 2s, 4s \
+                and so on. Without --shard-size only fetches that failed before their first \
+                record are retried",
+        default_value = "0"
+    )]
+    pub retries: usize,
+
+    #[structopt(long, global = true, help = "log the progress of the fetches")]
+    pub progress: bool,
+
+    #[structopt(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "the doveadm command to run",
+        default_value = "doveadm",
+        parse(from_os_str)
+    )]
+    pub doveadm: PathBuf,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<CmdArgs, structopt::clap::Error> {
+        CmdArgs::from_iter_safe(std::iter::once("analyse").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_user_selection() {
+        let args = parse(&["senders", "-u", "alice@example.com"]).unwrap();
+        assert_eq!(args.user.as_deref(), Some("alice@example.com"));
+        assert!(!args.all_users);
+
+        assert!(parse(&["senders", "--all-users"]).unwrap().all_users);
+        assert_eq!(
+            parse(&["senders", "--user-mask", "*@example.com"])
+                .unwrap()
+                .user_mask
+                .as_deref(),
+            Some("*@example.com")
+        );
+
+        // global options can not be required, a missing user is reported by the commands
+        assert!(parse(&["senders"]).unwrap().user.is_none());
+        assert!(parse(&["senders", "-u", "alice@example.com", "--all-users"]).is_err());
+        assert!(parse(&["senders", "--all-users", "--user-mask", "*"]).is_err());
+    }
+
+    #[test]
+    fn test_commands() {
+        // global options go before or after the command
+        let args = parse(&["-u", "alice@example.com", "sizes", "--top", "5", "-j", "4"]).unwrap();
+        assert!(matches!(args.command, AnalyseCmd::Sizes { top: 5 }));
+        assert_eq!(args.user.as_deref(), Some("alice@example.com"));
+        assert_eq!(args.jobs, 4);
+        assert_eq!(args.output, ReportFormat::Text);
+        let args = parse(&["--output", "tsv", "folders", "-u", "bob"]).unwrap();
+        assert_eq!(args.output, ReportFormat::Tsv);
+        assert!(parse(&["folders", "-u", "bob", "-o", "json"]).is_err());
+
+        let args = parse(&["fetch", "-u", "bob", "-f", "uid", "-f", "hdr.subject"]).unwrap();
+        match args.command {
+            AnalyseCmd::Fetch { fields } => assert_eq!(
+                fields,
+                vec![ImapField::Uid, ImapField::HdrField("subject".to_owned())]
+            ),
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert!(parse(&["fetch", "-u", "bob"]).is_err());
+
+        let args = parse(&[
+            "duplicates",
+            "--all-users",
+            "--keep",
+            "most-flags",
+            "--expunge",
+        ])
+        .unwrap();
+        match args.command {
+            AnalyseCmd::Duplicates(args) => {
+                assert_eq!(args.keep, KeepPolicy::MostFlags);
+                assert!(args.expunge && !args.execute);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert!(parse(&[
+            "duplicates",
+            "-u",
+            "bob",
+            "--expunge",
+            "--quarantine",
+            "Junk"
+        ])
+        .is_err());
+        // there is nothing to execute without a removal method
+        assert!(parse(&["duplicates", "-u", "bob", "--execute"]).is_err());
+        assert!(parse(&[
+            "duplicates",
+            "-u",
+            "bob",
+            "--execute",
+            "--quarantine",
+            "Junk"
+        ])
+        .is_ok());
+        // command options belong to their command
+        assert!(parse(&["senders", "-u", "bob", "--min-size", "10"]).is_err());
+        assert!(parse(&["-u", "bob"]).is_err());
+    }
 }
@@ -0,0 +1,152 @@
+use crate::doveadm::{DoveadmError, SearchParam, DOVEADM_CMD};
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use std::ffi::{OsStr, OsString};
+use std::process::{Command, Stdio};
+
+/// Runs doveadm commands whose output is small enough to be collected at once, unlike fetch
+/// which is streamed by DoveadmFetch
+#[derive(Clone, Debug)]
+pub struct DoveadmCmd {
+    cmd: OsString,
+}
+
+impl Default for DoveadmCmd {
+    fn default() -> Self {
+        DoveadmCmd::new()
+    }
+}
+
+impl DoveadmCmd {
+    pub fn new() -> DoveadmCmd {
+        DoveadmCmd::with_cmd(DOVEADM_CMD)
+    }
+
+    /// Run cmd instead of doveadm
+    pub fn with_cmd<S: AsRef<OsStr>>(cmd: S) -> DoveadmCmd {
+        DoveadmCmd {
+            cmd: cmd.as_ref().to_owned(),
+        }
+    }
+
+    /// Run doveadm with args and return its stdout, fails with a DoveadmError if doveadm was not
+    /// successful
+    pub fn run(&self, args: &[String]) -> Result<Vec<u8>> {
+        debug!(
+            "DoveadmCmd::run: running command: {:?} params: {:?}",
+            self.cmd, args
+        );
+        let output = Command::new(&self.cmd)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("failed to run doveadm {}", args.join(" ")))?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            let stderr_lines = String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .map(|line| line.to_owned())
+                .collect();
+            Err(DoveadmError::new(&output.status, stderr_lines, args.to_vec()).into())
+        }
+    }
+
+    /// Move the messages of user matching search to the mailbox destination
+    pub fn move_mails(&self, user: &str, destination: &str, search: &[SearchParam]) -> Result<()> {
+        self.run(&move_args(user, destination, search)).map(|_| ())
+    }
+
+    /// Expunge the messages of user matching search
+    pub fn expunge(&self, user: &str, search: &[SearchParam]) -> Result<()> {
+        self.run(&expunge_args(user, search)).map(|_| ())
+    }
+
+    /// The mailbox GUIDs and UIDs of the messages of user matching search
+    pub fn search(&self, user: &str, search: &[SearchParam]) -> Result<Vec<(String, u64)>> {
+        parse_search_output(&self.run(&search_args(user, search))?)
+    }
+}
+
+fn move_args(user: &str, destination: &str, search: &[SearchParam]) -> Vec<String> {
+    let mut args = vec![
+        "move".to_owned(),
+        "-u".to_owned(),
+        user.to_owned(),
+        destination.to_owned(),
+    ];
+    args.extend(search.iter().flat_map(SearchParam::to_params));
+    args
+}
+
+fn expunge_args(user: &str, search: &[SearchParam]) -> Vec<String> {
+    let mut args = vec!["expunge".to_owned(), "-u".to_owned(), user.to_owned()];
+    args.extend(search.iter().flat_map(SearchParam::to_params));
+    args
+}
+
+fn search_args(user: &str, search: &[SearchParam]) -> Vec<String> {
+    let mut args = vec!["search".to_owned(), "-u".to_owned(), user.to_owned()];
+    args.extend(search.iter().flat_map(SearchParam::to_params));
+    args
+}
+
+// doveadm search prints a line of mailbox GUID and UID for every match
+fn parse_search_output(output: &[u8]) -> Result<Vec<(String, u64)>> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next().map(|uid| uid.parse::<u64>())) {
+                (Some(mailbox_guid), Some(Ok(uid))) => Ok((mailbox_guid.to_owned(), uid)),
+                _ => Err(anyhow!("invalid doveadm search output '{}'", line)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doveadm::{SeqElement, SeqSet};
+
+    #[test]
+    fn test_args() {
+        let search = [
+            SearchParam::MailboxGuid("mb1".to_owned()),
+            SearchParam::Uid(SeqSet::new(SeqElement::Uid(7))),
+        ];
+        assert_eq!(
+            move_args("alice", "Sent Items", &search),
+            vec![
+                "move",
+                "-u",
+                "alice",
+                "Sent Items",
+                "MAILBOX-GUID",
+                "mb1",
+                "UID",
+                "7"
+            ]
+        );
+        assert_eq!(
+            expunge_args("alice", &search),
+            vec!["expunge", "-u", "alice", "MAILBOX-GUID", "mb1", "UID", "7"]
+        );
+        assert_eq!(
+            search_args("alice", &search),
+            vec!["search", "-u", "alice", "MAILBOX-GUID", "mb1", "UID", "7"]
+        );
+    }
+
+    #[test]
+    fn test_parse_search_output() {
+        assert_eq!(
+            parse_search_output(b"mb1 7\nmb2 12\n").unwrap(),
+            vec![("mb1".to_owned(), 7), ("mb2".to_owned(), 12)]
+        );
+        assert!(parse_search_output(b"").unwrap().is_empty());
+        assert!(parse_search_output(b"mb1 x\n").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::{Display, Formatter};
+use std::process::ExitStatus;
+
+// exit codes documented in doveadm(1), taken from sysexits.h
+const EX_NOUSER: i32 = 67;
+const EX_NOTFOUND: i32 = 68;
+const EX_TEMPFAIL: i32 = 75;
+const EX_NOPERM: i32 = 77;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoveadmErrorKind {
+    UnknownUser,
+    NoSuchMailbox,
+    TempFail,
+    NoPermission,
+    // terminated by a signal, there is no exit code
+    Signal,
+    Other,
+}
+
+impl DoveadmErrorKind {
+    pub fn from_exit_code(exit_code: Option<i32>) -> DoveadmErrorKind {
+        match exit_code {
+            Some(EX_NOUSER) => DoveadmErrorKind::UnknownUser,
+            Some(EX_NOTFOUND) => DoveadmErrorKind::NoSuchMailbox,
+            Some(EX_TEMPFAIL) => DoveadmErrorKind::TempFail,
+            Some(EX_NOPERM) => DoveadmErrorKind::NoPermission,
+            Some(_) => DoveadmErrorKind::Other,
+            None => DoveadmErrorKind::Signal,
+        }
+    }
+
+    /// Whether running the command again may succeed, ie. doveadm reported a temporary failure or
+    /// was killed. Other failures, including unknown exit codes, are permanent.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DoveadmErrorKind::TempFail | DoveadmErrorKind::Signal)
+    }
+}
+
+impl Display for DoveadmErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DoveadmErrorKind::UnknownUser => "unknown user",
+            DoveadmErrorKind::NoSuchMailbox => "no such mailbox",
+            DoveadmErrorKind::TempFail => "temporary failure",
+            DoveadmErrorKind::NoPermission => "no permission",
+            DoveadmErrorKind::Signal => "terminated by signal",
+            DoveadmErrorKind::Other => "command failed",
+        })
+    }
+}
+
+/// A doveadm command that terminated unsuccessfully
+#[derive(Debug, Clone)]
+pub struct DoveadmError {
+    pub kind: DoveadmErrorKind,
+    pub exit_code: Option<i32>,
+    pub stderr_lines: Vec<String>,
+    pub args: Vec<String>,
+}
+
+impl DoveadmError {
+    pub fn new(status: &ExitStatus, stderr_lines: Vec<String>, args: Vec<String>) -> DoveadmError {
+        DoveadmError {
+            kind: DoveadmErrorKind::from_exit_code(status.code()),
+            exit_code: status.code(),
+            stderr_lines,
+            args,
+        }
+    }
+}
+
+impl Display for DoveadmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "doveadm {}: {}", self.args.join(" "), self.kind)?;
+        if let Some(exit_code) = self.exit_code {
+            write!(f, " (exit code {})", exit_code)?;
+        }
+        for line in &self.stderr_lines {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DoveadmError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_exit_code() {
+        assert_eq!(
+            DoveadmErrorKind::from_exit_code(Some(67)),
+            DoveadmErrorKind::UnknownUser
+        );
+        assert_eq!(
+            DoveadmErrorKind::from_exit_code(Some(68)),
+            DoveadmErrorKind::NoSuchMailbox
+        );
+        assert_eq!(
+            DoveadmErrorKind::from_exit_code(Some(75)),
+            DoveadmErrorKind::TempFail
+        );
+        assert_eq!(
+            DoveadmErrorKind::from_exit_code(Some(77)),
+            DoveadmErrorKind::NoPermission
+        );
+        assert_eq!(
+            DoveadmErrorKind::from_exit_code(Some(1)),
+            DoveadmErrorKind::Other
+        );
+        assert_eq!(
+            DoveadmErrorKind::from_exit_code(None),
+            DoveadmErrorKind::Signal
+        );
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(DoveadmErrorKind::TempFail.is_transient());
+        assert!(DoveadmErrorKind::Signal.is_transient());
+        assert!(!DoveadmErrorKind::Other.is_transient());
+        assert!(!DoveadmErrorKind::UnknownUser.is_transient());
+        assert!(!DoveadmErrorKind::NoPermission.is_transient());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::doveadm::{DoveadmCmd, DoveadmError, DoveadmErrorKind};
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use std::ffi::OsStr;
+
+// the fields requested from doveadm mailbox status
+const STATUS_FIELDS: &str = "messages vsize unseen recent uidnext highestmodseq guid";
+
+/// The status of a mailbox as reported by doveadm mailbox status
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MailboxInfo {
+    pub name: String,
+    pub guid: String,
+    pub messages: u64,
+    /// The virtual size, the size of all messages with CRLF line endings
+    pub vsize: u64,
+    pub unseen: u64,
+    pub recent: u64,
+    /// The UID the next message delivered will get, all messages have lower UIDs
+    pub uid_next: u64,
+    pub highest_modseq: u64,
+}
+
+impl MailboxInfo {
+    // parse the name=value pairs of the flow formatter
+    fn parse(name: &str, output: &str) -> Result<MailboxInfo> {
+        let mut info = MailboxInfo {
+            name: name.to_owned(),
+            ..MailboxInfo::default()
+        };
+        for pair in output.split_whitespace() {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid mailbox status field '{}'", pair))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .with_context(|| format!("invalid mailbox status value '{}'", pair))
+            };
+            match key {
+                "messages" => info.messages = number()?,
+                "vsize" => info.vsize = number()?,
+                "unseen" => info.unseen = number()?,
+                "recent" => info.recent = number()?,
+                "uidnext" => info.uid_next = number()?,
+                "highestmodseq" => info.highest_modseq = number()?,
+                "guid" => info.guid = value.to_owned(),
+                _ => debug!("MailboxInfo::parse: ignoring field {}", pair),
+            }
+        }
+        if info.guid.is_empty() {
+            Err(anyhow!("mailbox status of {} lacks the guid", name))
+        } else {
+            Ok(info)
+        }
+    }
+}
+
+/// Enumerates the mailboxes of a user with doveadm mailbox list and mailbox status
+pub struct DoveadmMailboxList {
+    cmd: DoveadmCmd,
+    user: String,
+}
+
+impl DoveadmMailboxList {
+    pub fn new(user: String) -> DoveadmMailboxList {
+        DoveadmMailboxList {
+            cmd: DoveadmCmd::new(),
+            user,
+        }
+    }
+
+    /// Run cmd instead of doveadm, cmd is expected to behave like 'doveadm mailbox'
+    pub fn with_cmd<S: AsRef<OsStr>>(cmd: S, user: String) -> DoveadmMailboxList {
+        DoveadmMailboxList {
+            cmd: DoveadmCmd::with_cmd(cmd),
+            user,
+        }
+    }
+
+    /// The names of all mailboxes of the user
+    pub fn list(&self) -> Result<Vec<String>> {
+        let output = self.cmd.run(&[
+            "mailbox".to_owned(),
+            "list".to_owned(),
+            "-u".to_owned(),
+            self.user.clone(),
+        ])?;
+        // mailbox names are modified UTF-7 encoded in storage, doveadm prints them as UTF-8
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_owned())
+            .collect())
+    }
+
+    pub fn status(&self, mailbox: &str) -> Result<MailboxInfo> {
+        let output = self.cmd.run(&[
+            "mailbox".to_owned(),
+            "status".to_owned(),
+            "-u".to_owned(),
+            self.user.clone(),
+            STATUS_FIELDS.to_owned(),
+            mailbox.to_owned(),
+        ])?;
+        MailboxInfo::parse(mailbox, &String::from_utf8_lossy(&output))
+            .with_context(|| format!("doveadm mailbox status {}", mailbox))
+    }
+
+    /// The status of all mailboxes of the user in the order listed. Mailboxes that can not be
+    /// selected, ie. that were deleted since they were listed or exist only as parents of other
+    /// mailboxes, are left out.
+    pub fn mailboxes(&self) -> Result<Vec<MailboxInfo>> {
+        let mut res = Vec::new();
+        for mailbox in self.list()? {
+            match self.status(&mailbox) {
+                Ok(info) => res.push(info),
+                Err(err)
+                    if err.downcast_ref::<DoveadmError>().map(|err| err.kind)
+                        == Some(DoveadmErrorKind::NoSuchMailbox) =>
+                {
+                    warn!(
+                        "DoveadmMailboxList::mailboxes: skipping {}: {:#}",
+                        mailbox, err
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let info = MailboxInfo::parse(
+            "INBOX",
+            "messages=3 vsize=12045 unseen=1 recent=0 uidnext=42 highestmodseq=17 \
+             guid=0b1f6c2a\n",
+        )
+        .unwrap();
+        assert_eq!(info.name, "INBOX");
+        assert_eq!(info.guid, "0b1f6c2a");
+        assert_eq!((info.messages, info.vsize, info.unseen), (3, 12045, 1));
+        assert_eq!((info.uid_next, info.highest_modseq), (42, 17));
+
+        assert!(MailboxInfo::parse("INBOX", "messages=x guid=1").is_err());
+        assert!(MailboxInfo::parse("INBOX", "messages=3").is_err());
+    }
+}
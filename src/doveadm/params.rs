@@ -1,16 +1,23 @@
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, Context, Error, Result};
 use chrono::NaiveDate;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use std::string::ToString;
-use std::time::Instant;
-use strum_macros;
 
-#[derive(Debug)]
+mod query;
+pub use query::{parse_query, parse_query_args};
+
+// UIDs are 32 bit numbers, RFC 3501 section 2.3.1.1
+const MAX_UID: u64 = u32::MAX as u64;
+
+#[derive(Clone, Debug)]
 pub struct FetchParams {
     user: String,
     fields: Vec<ImapField>,
     search: Vec<SearchParam>,
+    format: Option<OutputFormat>,
+    // created by shards
+    shard: bool,
 }
 
 impl FetchParams {
@@ -19,6 +26,30 @@ impl FetchParams {
             user,
             fields: Vec::new(),
             search: Vec::new(),
+            format: None,
+            shard: false,
+        }
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Select the doveadm output formatter, by default it is chosen by output_format
+    pub fn set_format(&mut self, format: OutputFormat) -> &mut Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// The formatter doveadm is run with - tab if not set explicitly and all fields are single line
+    /// fields, pager otherwise
+    pub fn output_format(&self) -> OutputFormat {
+        if let Some(format) = self.format {
+            format
+        } else if self.fields.iter().all(|field| field.is_single_line()) {
+            OutputFormat::Tab
+        } else {
+            OutputFormat::Pager
         }
     }
 
@@ -35,8 +66,19 @@ impl FetchParams {
     pub fn to_args(&self) -> Result<Vec<String>> {
         let mut args = vec!["fetch".to_owned()];
 
+        let format = self.output_format();
+        if format != OutputFormat::Pager {
+            if let Some(field) = self.fields.iter().find(|field| !field.is_single_line()) {
+                return Err(anyhow!(
+                    "field {} can not be fetched with the {} formatter",
+                    field,
+                    format
+                ));
+            }
+        }
+
         args.push("-f".to_string());
-        args.push("pager".to_string());
+        args.push(format.to_string());
 
         args.push("-u".to_string());
         args.push(self.user.clone());
@@ -44,12 +86,14 @@ impl FetchParams {
         if self.fields.is_empty() {
             return Err(anyhow!("no fields in doveadm fetch params"));
         } else {
-            let mut fields = String::new();
-            self.fields.iter().for_each(|field| {
-                fields.push_str(field.to_string().as_str());
-                fields.push(' ')
-            });
-            args.push(format!("\"{}\"", fields.trim()));
+            // no shell involved, so the field list is passed as a single unquoted argument
+            args.push(
+                self.fields
+                    .iter()
+                    .map(|field| field.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            );
         }
 
         if self.search.is_empty() {
@@ -66,67 +110,241 @@ impl FetchParams {
     pub fn fields(&self) -> &Vec<ImapField> {
         &self.fields
     }
+
+    /// Whether the fetch is one of the UID ranges created by shards, a UID in the search alone
+    /// does not make a shard
+    pub fn is_shard(&self) -> bool {
+        self.shard
+    }
+
+    /// Split the fetch into fetches of consecutive UID ranges of shard_size UIDs, covering the
+    /// UIDs below uid_next as reported by doveadm mailbox status. UIDs are per mailbox, so the
+    /// search has to be restricted to a single mailbox. The last range is open-ended, so messages
+    /// delivered after uid_next was determined are fetched too.
+    ///
+    /// Without a shard size or with an empty mailbox the fetch is not split.
+    pub fn shards(&self, uid_next: u64, shard_size: u64) -> Vec<FetchParams> {
+        if shard_size == 0 || uid_next <= 1 {
+            return vec![self.clone()];
+        }
+        let mut res = Vec::new();
+        let mut start = 1;
+        while start < uid_next {
+            // 'start:*' would match the last message even if its UID is below start, IMAP swaps
+            // the bounds of a range, so the highest UID possible is given instead
+            let end = if start + shard_size < uid_next {
+                start + shard_size - 1
+            } else {
+                MAX_UID
+            };
+            let mut shard = self.clone();
+            shard.shard = true;
+            shard.add_search_param(SearchParam::Uid(SeqSet::new(SeqElement::Range(
+                start as usize,
+                end as usize,
+            ))));
+            res.push(shard);
+            start = end + 1;
+        }
+        res
+    }
 }
 
-trait ToParam {
-    fn to_param(&self) -> String;
+/// The doveadm output formatters that can be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, strum_macros::Display)]
+pub enum OutputFormat {
+    // one line per field, multi line fields on subsequent lines, records separated by form feeds
+    #[strum(serialize = "pager")]
+    Pager,
+    // a line of field names followed by one line per record with tab separated values
+    #[strum(serialize = "tab")]
+    Tab,
+    // one line per record with space separated name=value pairs
+    #[strum(serialize = "flow")]
+    Flow,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pager" => Ok(OutputFormat::Pager),
+            "tab" => Ok(OutputFormat::Tab),
+            "flow" => Ok(OutputFormat::Flow),
+            _ => Err(anyhow!("invalid output format {}", s)),
+        }
+    }
 }
 
-trait ToParams {
-    fn to_params(&self) -> Vec<String>;
+trait ToParam {
+    fn to_param(&self) -> String;
 }
 
-#[derive(Clone, Debug, strum_macros::Display)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ImapField {
-    #[strum(serialize = "hdr")]
     Hdr,
-    #[strum(serialize = "flags")]
+    // a single header, hdr.<name>
+    HdrField(String),
     Flags,
-    #[strum(serialize = "body")]
     Body,
-    #[strum(serialize = "date.received")]
+    BodySnippet,
+    Text,
+    TextUtf8,
     DateReceived,
-    #[strum(serialize = "date.saved")]
     DateSaved,
-    #[strum(serialize = "date.sent")]
     DateSent,
-    #[strum(serialize = "guid")]
+    DateReceivedUnixtime,
+    DateSavedUnixtime,
+    DateSentUnixtime,
     Guid,
-    #[strum(serialize = "imap.body")]
     ImapBody,
-    #[strum(serialize = "imap.bodystructure")]
     ImapBodystructure,
-    #[strum(serialize = "imap.envelope")]
     ImapEnvelope,
-    #[strum(serialize = "mailbox")]
     Mailbox,
-    #[strum(serialize = "mailbox-guid")]
     MailboxGuid,
+    Modseq,
+    Pop3Order,
+    Pop3Uidl,
+    Refcount,
+    Seq,
+    SizePhysical,
+    SizeVirtual,
+    Storageid,
+    Uid,
+    User,
+}
+
+impl ImapField {
+    /// Fields whose value never spans more than one line, only these can be fetched with the tab
+    /// and flow formatters
+    pub fn is_single_line(&self) -> bool {
+        !matches!(
+            self,
+            ImapField::Hdr
+                | ImapField::HdrField(_)
+                | ImapField::Body
+                | ImapField::Text
+                | ImapField::TextUtf8
+                | ImapField::ImapBody
+                | ImapField::ImapBodystructure
+                | ImapField::ImapEnvelope
+        )
+    }
+
+    /// Fields with a numeric value. pop3.order is not among them, it is empty for messages that
+    /// were not migrated from POP3.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            ImapField::Modseq
+                | ImapField::Refcount
+                | ImapField::Seq
+                | ImapField::SizePhysical
+                | ImapField::SizeVirtual
+                | ImapField::Uid
+        )
+    }
+
+    /// Fields with a date value
+    pub fn is_date(&self) -> bool {
+        matches!(
+            self,
+            ImapField::DateReceived
+                | ImapField::DateSaved
+                | ImapField::DateSent
+                | ImapField::DateReceivedUnixtime
+                | ImapField::DateSavedUnixtime
+                | ImapField::DateSentUnixtime
+        )
+    }
+}
+
+impl Display for ImapField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ImapField::Hdr => "hdr",
+            ImapField::HdrField(name) => return write!(f, "hdr.{}", name),
+            ImapField::Flags => "flags",
+            ImapField::Body => "body",
+            ImapField::BodySnippet => "body.snippet",
+            ImapField::Text => "text",
+            ImapField::TextUtf8 => "text.utf8",
+            ImapField::DateReceived => "date.received",
+            ImapField::DateSaved => "date.saved",
+            ImapField::DateSent => "date.sent",
+            ImapField::DateReceivedUnixtime => "date.received.unixtime",
+            ImapField::DateSavedUnixtime => "date.saved.unixtime",
+            ImapField::DateSentUnixtime => "date.sent.unixtime",
+            ImapField::Guid => "guid",
+            ImapField::ImapBody => "imap.body",
+            ImapField::ImapBodystructure => "imap.bodystructure",
+            ImapField::ImapEnvelope => "imap.envelope",
+            ImapField::Mailbox => "mailbox",
+            ImapField::MailboxGuid => "mailbox-guid",
+            ImapField::Modseq => "modseq",
+            ImapField::Pop3Order => "pop3.order",
+            ImapField::Pop3Uidl => "pop3.uidl",
+            ImapField::Refcount => "refcount",
+            ImapField::Seq => "seq",
+            ImapField::SizePhysical => "size.physical",
+            ImapField::SizeVirtual => "size.virtual",
+            ImapField::Storageid => "storageid",
+            ImapField::Uid => "uid",
+            ImapField::User => "user",
+        };
+        f.write_str(name)
+    }
 }
 
 impl FromStr for ImapField {
     type Err = Error;
 
+    // accepts the field names used by doveadm fetch as well as the names without separators
     fn from_str(s: &str) -> Result<Self> {
-        match s.to_lowercase().as_str() {
+        let name = s.to_lowercase();
+        if let Some(hdr_name) = name.strip_prefix("hdr.") {
+            return if hdr_name.is_empty() {
+                Err(anyhow!("invalid field name {}, missing header name", s))
+            } else {
+                Ok(ImapField::HdrField(s[4..].to_owned()))
+            };
+        }
+        match name.as_str() {
             "hdr" => Ok(ImapField::Hdr),
             "flags" => Ok(ImapField::Flags),
             "body" => Ok(ImapField::Body),
-            "datereceived" => Ok(ImapField::DateReceived),
-            "datesaved" => Ok(ImapField::DateSaved),
-            "datesent" => Ok(ImapField::DateSent),
+            "body.snippet" | "bodysnippet" => Ok(ImapField::BodySnippet),
+            "text" => Ok(ImapField::Text),
+            "text.utf8" | "textutf8" => Ok(ImapField::TextUtf8),
+            "date.received" | "datereceived" => Ok(ImapField::DateReceived),
+            "date.saved" | "datesaved" => Ok(ImapField::DateSaved),
+            "date.sent" | "datesent" => Ok(ImapField::DateSent),
+            "date.received.unixtime" => Ok(ImapField::DateReceivedUnixtime),
+            "date.saved.unixtime" => Ok(ImapField::DateSavedUnixtime),
+            "date.sent.unixtime" => Ok(ImapField::DateSentUnixtime),
             "guid" => Ok(ImapField::Guid),
             "imap.body" => Ok(ImapField::ImapBody),
             "imap.bodystructure" | "bodystructure" => Ok(ImapField::ImapBodystructure),
             "imap.envelope" | "envelope" => Ok(ImapField::ImapEnvelope),
             "mailbox" => Ok(ImapField::Mailbox),
             "mailboxguid" | "mailbox-guid" => Ok(ImapField::MailboxGuid),
+            "modseq" => Ok(ImapField::Modseq),
+            "pop3.order" => Ok(ImapField::Pop3Order),
+            "pop3.uidl" => Ok(ImapField::Pop3Uidl),
+            "refcount" => Ok(ImapField::Refcount),
+            "seq" => Ok(ImapField::Seq),
+            "size.physical" | "sizephysical" => Ok(ImapField::SizePhysical),
+            "size.virtual" | "sizevirtual" => Ok(ImapField::SizeVirtual),
+            "storageid" => Ok(ImapField::Storageid),
+            "uid" => Ok(ImapField::Uid),
+            "user" => Ok(ImapField::User),
             _ => Err(anyhow!("invalid field name {}", s)),
         }
     }
 }
 
-#[derive(Debug, strum_macros::Display)]
+#[derive(Clone, Debug, PartialEq, Eq, strum_macros::Display)]
 pub enum SearchParam {
     SequenceSet(SeqSet),
     All,
@@ -139,6 +357,8 @@ pub enum SearchParam {
     Draft,
     Flagged,
     From(String),
+    // the message GUID
+    Guid(String),
     Header(String, Option<String>),
     Keyword(String),
     Larger(usize),
@@ -150,6 +370,8 @@ pub enum SearchParam {
     Old,
     On(DateSpec),
     Or(Box<SearchParam>, Box<SearchParam>),
+    // a parenthesised list of search params, all of which have to match
+    Group(Vec<SearchParam>),
     Recent,
     Seen,
     SentBefore(DateSpec),
@@ -172,6 +394,21 @@ pub enum SearchParam {
     SavedSince(DateSpec),
 }
 
+impl FromStr for SearchParam {
+    type Err = Error;
+
+    /// Parse a doveadm style search query, a query consisting of more than one search key is
+    /// returned as a Group
+    fn from_str(s: &str) -> Result<Self> {
+        let mut params = parse_query(s)?;
+        if params.len() == 1 {
+            Ok(params.remove(0))
+        } else {
+            Ok(SearchParam::Group(params))
+        }
+    }
+}
+
 impl SearchParam {
     fn to_dc_name(&self) -> String {
         self.to_string().to_uppercase()
@@ -190,12 +427,14 @@ impl SearchParam {
             SearchParam::Draft => vec![self.to_dc_name()],
             SearchParam::Flagged => vec![self.to_dc_name()],
             SearchParam::From(comp) => vec![self.to_dc_name(), comp.to_owned()],
+            SearchParam::Guid(comp) => vec![self.to_dc_name(), comp.to_owned()],
             SearchParam::Header(hdr, comp) => {
-                if let Some(comp) = comp {
-                    vec![self.to_dc_name(), hdr.to_string(), comp.to_string()]
-                } else {
-                    vec![self.to_dc_name(), hdr.to_string()]
-                }
+                // HEADER always takes two arguments, an empty string matches any value
+                vec![
+                    self.to_dc_name(),
+                    hdr.to_string(),
+                    comp.clone().unwrap_or_default(),
+                ]
             }
             SearchParam::Keyword(comp) => vec![self.to_dc_name(), comp.to_owned()],
             SearchParam::Larger(size) => vec![self.to_dc_name(), size.to_string()],
@@ -210,9 +449,18 @@ impl SearchParam {
             SearchParam::Old => vec![self.to_dc_name()],
             SearchParam::On(date) => vec![self.to_dc_name(), date.to_param()],
             SearchParam::Or(param1, param2) => {
-                let mut res = param1.to_params();
-                res.push(self.to_dc_name());
-                res.append(&mut (*param2).to_params());
+                // doveadm expects OR in prefix notation: OR <param1> <param2>
+                let mut res = vec![self.to_dc_name()];
+                res.append(&mut param1.to_params());
+                res.append(&mut param2.to_params());
+                res
+            }
+            SearchParam::Group(params) => {
+                let mut res = vec!["(".to_owned()];
+                for param in params {
+                    res.append(&mut param.to_params());
+                }
+                res.push(")".to_owned());
                 res
             }
             SearchParam::Recent => vec![self.to_dc_name()],
@@ -239,18 +487,33 @@ impl SearchParam {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DateSpec(NaiveDate);
 
 impl DateSpec {
-    pub fn from_ymd(year: i32, month: u32, day: u32) -> DateSpec {
-        DateSpec(NaiveDate::from_ymd(year, month, day))
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Result<DateSpec> {
+        Ok(DateSpec(
+            NaiveDate::from_ymd_opt(year, month, day)
+                .ok_or_else(|| anyhow!("invalid date {}-{}-{}", year, month, day))?,
+        ))
     }
     pub fn today() -> DateSpec {
         DateSpec(chrono::Local::now().date_naive())
     }
 }
 
+impl FromStr for DateSpec {
+    type Err = Error;
+
+    // accepts ISO dates (2022-01-31) as well as IMAP dates (31-Jan-2022)
+    fn from_str(s: &str) -> Result<Self> {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(s, "%d-%b-%Y"))
+            .map(DateSpec)
+            .map_err(|_| anyhow!("invalid date '{}'", s))
+    }
+}
+
 impl ToParam for DateSpec {
     fn to_param(&self) -> String {
         self.0.format("%Y-%m-%d").to_string()
@@ -264,7 +527,7 @@ impl ToParam for DateSpec {
    For example 1:100 matches the first 100 mails and 101:200 the next second hundred mails. 1,5,* matches the first, the fifth and the last email.
 */
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SeqSet(Vec<SeqElement>);
 
 impl SeqSet {
@@ -276,9 +539,22 @@ impl SeqSet {
     }
 }
 
+impl FromStr for SeqSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let elements = s
+            .split(',')
+            .map(|el| el.parse::<SeqElement>())
+            .collect::<Result<Vec<SeqElement>>>()
+            .with_context(|| format!("invalid sequence set '{}'", s))?;
+        Ok(SeqSet(elements))
+    }
+}
+
 impl ToParam for SeqSet {
     fn to_param(&self) -> String {
-        if let Some(first) = self.0.get(0) {
+        if let Some(first) = self.0.first() {
             let mut res = first.to_param();
             for el in self.0.iter().skip(1) {
                 res.push(',');
@@ -291,13 +567,27 @@ impl ToParam for SeqSet {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum SeqElement {
     Uid(usize),
     Range(usize, usize),
     Last,
 }
 
+impl FromStr for SeqElement {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "*" {
+            Ok(SeqElement::Last)
+        } else if let Some((start, end)) = s.split_once(':') {
+            Ok(SeqElement::Range(start.parse()?, end.parse()?))
+        } else {
+            Ok(SeqElement::Uid(s.parse()?))
+        }
+    }
+}
+
 impl ToParam for SeqElement {
     fn to_param(&self) -> String {
         match self {
@@ -307,3 +597,285 @@ impl ToParam for SeqElement {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date() -> DateSpec {
+        DateSpec::from_ymd(2022, 1, 31).unwrap()
+    }
+
+    fn seq_set() -> SeqSet {
+        let mut set = SeqSet::new(SeqElement::Uid(1));
+        set.add(SeqElement::Range(5, 10));
+        set.add(SeqElement::Last);
+        set
+    }
+
+    #[test]
+    fn test_to_params() {
+        let table: Vec<(SearchParam, &[&str])> = vec![
+            (SearchParam::SequenceSet(seq_set()), &["1,5:10,*"]),
+            (SearchParam::All, &["ALL"]),
+            (SearchParam::Answered, &["ANSWERED"]),
+            (SearchParam::Bcc("bob".to_owned()), &["BCC", "bob"]),
+            (SearchParam::Before(date()), &["BEFORE", "2022-01-31"]),
+            (
+                SearchParam::Body("some text".to_owned()),
+                &["BODY", "some text"],
+            ),
+            (SearchParam::CC("bob".to_owned()), &["CC", "bob"]),
+            (SearchParam::Deleted, &["DELETED"]),
+            (SearchParam::Draft, &["DRAFT"]),
+            (SearchParam::Flagged, &["FLAGGED"]),
+            (SearchParam::From("alice".to_owned()), &["FROM", "alice"]),
+            (SearchParam::Guid("g1".to_owned()), &["GUID", "g1"]),
+            (
+                SearchParam::Header("X-Spam".to_owned(), Some("yes".to_owned())),
+                &["HEADER", "X-Spam", "yes"],
+            ),
+            (
+                SearchParam::Header("X-Spam".to_owned(), None),
+                &["HEADER", "X-Spam", ""],
+            ),
+            (
+                SearchParam::Keyword("$Junk".to_owned()),
+                &["KEYWORD", "$Junk"],
+            ),
+            (SearchParam::Larger(1024), &["LARGER", "1024"]),
+            (
+                SearchParam::Mailbox("Sent Items".to_owned()),
+                &["MAILBOX", "Sent Items"],
+            ),
+            (
+                SearchParam::MailboxGuid("abc".to_owned()),
+                &["MAILBOX-GUID", "abc"],
+            ),
+            (SearchParam::New, &["NEW"]),
+            (
+                SearchParam::Not(Box::new(SearchParam::Seen)),
+                &["NOT", "SEEN"],
+            ),
+            (SearchParam::Old, &["OLD"]),
+            (SearchParam::On(date()), &["ON", "2022-01-31"]),
+            (
+                SearchParam::Or(Box::new(SearchParam::Seen), Box::new(SearchParam::Flagged)),
+                &["OR", "SEEN", "FLAGGED"],
+            ),
+            (SearchParam::Recent, &["RECENT"]),
+            (SearchParam::Seen, &["SEEN"]),
+            (
+                SearchParam::SentBefore(date()),
+                &["SENTBEFORE", "2022-01-31"],
+            ),
+            (SearchParam::SentOn(date()), &["SENTON", "2022-01-31"]),
+            (SearchParam::SentSince(date()), &["SENTSINCE", "2022-01-31"]),
+            (SearchParam::Since(date()), &["SINCE", "2022-01-31"]),
+            (SearchParam::Smaller(10), &["SMALLER", "10"]),
+            (SearchParam::Subject("hi".to_owned()), &["SUBJECT", "hi"]),
+            (SearchParam::Text("hi".to_owned()), &["TEXT", "hi"]),
+            (SearchParam::To("bob".to_owned()), &["TO", "bob"]),
+            (SearchParam::Uid(seq_set()), &["UID", "1,5:10,*"]),
+            (SearchParam::Unanswered, &["UNANSWERED"]),
+            (SearchParam::Undeleted, &["UNDELETED"]),
+            (SearchParam::Undraft, &["UNDRAFT"]),
+            (SearchParam::Unflagged, &["UNFLAGGED"]),
+            (
+                SearchParam::Unkeyword("$Junk".to_owned()),
+                &["UNKEYWORD", "$Junk"],
+            ),
+            (SearchParam::Unseen, &["UNSEEN"]),
+            (
+                SearchParam::SavedBefore(date()),
+                &["SAVEDBEFORE", "2022-01-31"],
+            ),
+            (SearchParam::SavedOn(date()), &["SAVEDON", "2022-01-31"]),
+            (
+                SearchParam::SavedSince(date()),
+                &["SAVEDSINCE", "2022-01-31"],
+            ),
+            (
+                SearchParam::Group(vec![SearchParam::Seen, SearchParam::Flagged]),
+                &["(", "SEEN", "FLAGGED", ")"],
+            ),
+            // nested params
+            (
+                SearchParam::Not(Box::new(SearchParam::Or(
+                    Box::new(SearchParam::From("alice".to_owned())),
+                    Box::new(SearchParam::From("bob".to_owned())),
+                ))),
+                &["NOT", "OR", "FROM", "alice", "FROM", "bob"],
+            ),
+            (
+                SearchParam::Or(
+                    Box::new(SearchParam::Or(
+                        Box::new(SearchParam::Seen),
+                        Box::new(SearchParam::Not(Box::new(SearchParam::Deleted))),
+                    )),
+                    Box::new(SearchParam::Group(vec![
+                        SearchParam::Mailbox("INBOX".to_owned()),
+                        SearchParam::Since(date()),
+                    ])),
+                ),
+                &[
+                    "OR",
+                    "OR",
+                    "SEEN",
+                    "NOT",
+                    "DELETED",
+                    "(",
+                    "MAILBOX",
+                    "INBOX",
+                    "SINCE",
+                    "2022-01-31",
+                    ")",
+                ],
+            ),
+        ];
+
+        for (param, expected) in table {
+            assert_eq!(param.to_params(), expected, "params for {:?}", param);
+        }
+    }
+
+    #[test]
+    fn test_to_args() {
+        let mut params = FetchParams::new("user@example.com".to_owned());
+        params
+            .add_field(ImapField::Hdr)
+            .add_field(ImapField::Flags)
+            .add_search_param(SearchParam::Mailbox("INBOX".to_owned()))
+            .add_search_param(SearchParam::Or(
+                Box::new(SearchParam::Seen),
+                Box::new(SearchParam::Flagged),
+            ));
+        assert_eq!(params.output_format(), OutputFormat::Pager);
+        assert_eq!(
+            params.to_args().unwrap(),
+            vec![
+                "fetch",
+                "-f",
+                "pager",
+                "-u",
+                "user@example.com",
+                "hdr flags",
+                "MAILBOX",
+                "INBOX",
+                "OR",
+                "SEEN",
+                "FLAGGED"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_format() {
+        let mut params = FetchParams::new("user@example.com".to_owned());
+        params
+            .add_field(ImapField::Flags)
+            .add_field(ImapField::Guid)
+            .add_search_param(SearchParam::All);
+        assert_eq!(params.output_format(), OutputFormat::Tab);
+        assert_eq!(params.to_args().unwrap()[1..3], ["-f", "tab"]);
+
+        params.set_format(OutputFormat::Flow);
+        assert_eq!(params.to_args().unwrap()[1..3], ["-f", "flow"]);
+
+        params.add_field(ImapField::Hdr);
+        assert!(params.to_args().is_err());
+        params.set_format(OutputFormat::Pager);
+        assert!(params.to_args().is_ok());
+    }
+
+    #[test]
+    fn test_imap_field_names() {
+        let fields = [
+            ImapField::Hdr,
+            ImapField::HdrField("X-Spam-Score".to_owned()),
+            ImapField::Flags,
+            ImapField::Body,
+            ImapField::BodySnippet,
+            ImapField::Text,
+            ImapField::TextUtf8,
+            ImapField::DateReceived,
+            ImapField::DateSaved,
+            ImapField::DateSent,
+            ImapField::DateReceivedUnixtime,
+            ImapField::DateSavedUnixtime,
+            ImapField::DateSentUnixtime,
+            ImapField::Guid,
+            ImapField::ImapBody,
+            ImapField::ImapBodystructure,
+            ImapField::ImapEnvelope,
+            ImapField::Mailbox,
+            ImapField::MailboxGuid,
+            ImapField::Modseq,
+            ImapField::Pop3Order,
+            ImapField::Pop3Uidl,
+            ImapField::Refcount,
+            ImapField::Seq,
+            ImapField::SizePhysical,
+            ImapField::SizeVirtual,
+            ImapField::Storageid,
+            ImapField::Uid,
+            ImapField::User,
+        ];
+        for field in fields {
+            assert_eq!(field.to_string().parse::<ImapField>().unwrap(), field);
+        }
+        assert_eq!(
+            "HDR.Subject".parse::<ImapField>().unwrap(),
+            ImapField::HdrField("Subject".to_owned())
+        );
+        assert_eq!(
+            "Size.Physical".parse::<ImapField>().unwrap(),
+            ImapField::SizePhysical
+        );
+        assert!("hdr.".parse::<ImapField>().is_err());
+        assert!("size".parse::<ImapField>().is_err());
+    }
+
+    #[test]
+    fn test_shards() {
+        let mut params = FetchParams::new("user@example.com".to_owned());
+        params
+            .add_field(ImapField::Guid)
+            .add_search_param(SearchParam::Mailbox("INBOX".to_owned()));
+        let ranges = |uid_next: u64, shard_size: u64| {
+            params
+                .shards(uid_next, shard_size)
+                .iter()
+                .map(|shard| shard.to_args().unwrap()[5..].join(" "))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            ranges(8, 3),
+            vec![
+                "guid MAILBOX INBOX UID 1:3",
+                "guid MAILBOX INBOX UID 4:6",
+                "guid MAILBOX INBOX UID 7:4294967295"
+            ]
+        );
+        assert_eq!(
+            ranges(7, 3).last().unwrap(),
+            "guid MAILBOX INBOX UID 4:4294967295"
+        );
+        assert_eq!(ranges(8, 0), vec!["guid MAILBOX INBOX"]);
+        assert_eq!(ranges(1, 3), vec!["guid MAILBOX INBOX"]);
+        assert!(params.shards(8, 3).iter().all(FetchParams::is_shard));
+        assert!(!params.shards(8, 0)[0].is_shard());
+        params.add_search_param(SearchParam::Uid(SeqSet::new(SeqElement::Range(1, 3))));
+        assert!(!params.is_shard());
+    }
+
+    #[test]
+    fn test_to_args_empty() {
+        let mut params = FetchParams::new("user@example.com".to_owned());
+        assert!(params.to_args().is_err());
+        params.add_field(ImapField::Hdr);
+        assert!(params.to_args().is_err());
+        params.add_search_param(SearchParam::All);
+        assert!(params.to_args().is_ok());
+    }
+}
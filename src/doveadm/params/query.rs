@@ -0,0 +1,336 @@
+use crate::doveadm::params::{DateSpec, SearchParam, SeqSet};
+use anyhow::{anyhow, Error, Result};
+use std::str::FromStr;
+
+/// Parse a doveadm style search query like
+/// 'mailbox INBOX seen since 2022-01-01 OR from alice from bob NOT deleted'
+///
+/// Search keys are case insensitive, arguments containing whitespace can be double quoted.
+/// Errors report the (1 based) character position in the query.
+pub fn parse_query(query: &str) -> Result<Vec<SearchParam>> {
+    QueryParser::new(tokenize(query)?, "position", query.chars().count() + 1).parse_all()
+}
+
+/// Parse a search query that has already been split into arguments, as returned by
+/// SearchParam::to_params. Errors report the (1 based) argument index.
+pub fn parse_query_args(args: &[String]) -> Result<Vec<SearchParam>> {
+    let tokens = args
+        .iter()
+        .enumerate()
+        .map(|(idx, arg)| Token {
+            value: arg.clone(),
+            pos: idx + 1,
+            quoted: false,
+        })
+        .collect();
+    QueryParser::new(tokens, "argument", args.len() + 1).parse_all()
+}
+
+#[derive(Debug)]
+struct Token {
+    value: String,
+    // position of the token in the query
+    pos: usize,
+    // quoted tokens are never interpreted as search keys or parentheses
+    quoted: bool,
+}
+
+impl Token {
+    fn is(&self, value: &str) -> bool {
+        !self.quoted && self.value == value
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = query.chars().enumerate().peekable();
+    while let Some((pos, ch)) = chars.next() {
+        if ch.is_whitespace() {
+            continue;
+        } else if ch == '(' || ch == ')' {
+            tokens.push(Token {
+                value: ch.to_string(),
+                pos: pos + 1,
+                quoted: false,
+            });
+        } else if ch == '"' {
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, ch)) => value.push(ch),
+                        None => {
+                            return Err(anyhow!(
+                                "search query: unterminated quoted string at position {}",
+                                pos + 1
+                            ))
+                        }
+                    },
+                    Some((_, ch)) => value.push(ch),
+                    None => {
+                        return Err(anyhow!(
+                            "search query: unterminated quoted string at position {}",
+                            pos + 1
+                        ))
+                    }
+                }
+            }
+            tokens.push(Token {
+                value,
+                pos: pos + 1,
+                quoted: true,
+            });
+        } else {
+            let mut value = ch.to_string();
+            while let Some((_, ch)) =
+                chars.next_if(|(_, ch)| !ch.is_whitespace() && *ch != '(' && *ch != ')')
+            {
+                value.push(ch);
+            }
+            tokens.push(Token {
+                value,
+                pos: pos + 1,
+                quoted: false,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+struct QueryParser {
+    tokens: std::vec::IntoIter<Token>,
+    // how positions are named in error messages
+    pos_name: &'static str,
+    // position reported for errors at the end of the query
+    end_pos: usize,
+}
+
+impl QueryParser {
+    fn new(tokens: Vec<Token>, pos_name: &'static str, end_pos: usize) -> QueryParser {
+        QueryParser {
+            tokens: tokens.into_iter(),
+            pos_name,
+            end_pos,
+        }
+    }
+
+    fn error(&self, msg: String, pos: usize) -> Error {
+        anyhow!("search query: {} at {} {}", msg, self.pos_name, pos)
+    }
+
+    fn parse_all(&mut self) -> Result<Vec<SearchParam>> {
+        let mut params = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            if token.is(")") {
+                return Err(self.error("unbalanced ')'".to_owned(), token.pos));
+            }
+            params.push(self.parse_key(token)?);
+        }
+        if params.is_empty() {
+            Err(self.error("empty query".to_owned(), self.end_pos))
+        } else {
+            Ok(params)
+        }
+    }
+
+    fn expect_token(&mut self, what: &str, key: &Token) -> Result<Token> {
+        match self.tokens.next() {
+            Some(token) => Ok(token),
+            None => Err(self.error(
+                format!("expected {} after '{}'", what, key.value),
+                self.end_pos,
+            )),
+        }
+    }
+
+    fn string_arg(&mut self, key: &Token) -> Result<String> {
+        Ok(self.expect_token("a string", key)?.value)
+    }
+
+    fn parsed_arg<T: FromStr>(&mut self, what: &str, key: &Token) -> Result<T> {
+        let token = self.expect_token(what, key)?;
+        token.value.parse::<T>().map_err(|_| {
+            self.error(
+                format!("invalid {} '{}' after '{}'", what, token.value, key.value),
+                token.pos,
+            )
+        })
+    }
+
+    fn date_arg(&mut self, key: &Token) -> Result<DateSpec> {
+        self.parsed_arg("date", key)
+    }
+
+    fn size_arg(&mut self, key: &Token) -> Result<usize> {
+        self.parsed_arg("size", key)
+    }
+
+    fn key_arg(&mut self, key: &Token) -> Result<Box<SearchParam>> {
+        let token = self.expect_token("a search key", key)?;
+        Ok(Box::new(self.parse_key(token)?))
+    }
+
+    fn parse_key(&mut self, token: Token) -> Result<SearchParam> {
+        if token.is("(") {
+            let mut params = Vec::new();
+            loop {
+                let next = self.expect_token("')'", &token)?;
+                if next.is(")") {
+                    break;
+                }
+                params.push(self.parse_key(next)?);
+            }
+            return if params.is_empty() {
+                Err(self.error("empty parentheses".to_owned(), token.pos))
+            } else {
+                Ok(SearchParam::Group(params))
+            };
+        }
+
+        if token.quoted {
+            return Err(self.error(
+                format!("expected a search key, found string '{}'", token.value),
+                token.pos,
+            ));
+        }
+
+        Ok(match token.value.to_uppercase().as_str() {
+            "ALL" => SearchParam::All,
+            "ANSWERED" => SearchParam::Answered,
+            "BCC" => SearchParam::Bcc(self.string_arg(&token)?),
+            "BEFORE" => SearchParam::Before(self.date_arg(&token)?),
+            "BODY" => SearchParam::Body(self.string_arg(&token)?),
+            "CC" => SearchParam::CC(self.string_arg(&token)?),
+            "DELETED" => SearchParam::Deleted,
+            "DRAFT" => SearchParam::Draft,
+            "FLAGGED" => SearchParam::Flagged,
+            "FROM" => SearchParam::From(self.string_arg(&token)?),
+            "GUID" => SearchParam::Guid(self.string_arg(&token)?),
+            "HEADER" => {
+                let name = self.string_arg(&token)?;
+                let value = self.string_arg(&token)?;
+                // an empty value matches any message that has the header
+                SearchParam::Header(name, Some(value).filter(|value| !value.is_empty()))
+            }
+            "KEYWORD" => SearchParam::Keyword(self.string_arg(&token)?),
+            "LARGER" => SearchParam::Larger(self.size_arg(&token)?),
+            "MAILBOX" => SearchParam::Mailbox(self.string_arg(&token)?),
+            "MAILBOX-GUID" => SearchParam::MailboxGuid(self.string_arg(&token)?),
+            "NEW" => SearchParam::New,
+            "NOT" => SearchParam::Not(self.key_arg(&token)?),
+            "OLD" => SearchParam::Old,
+            "ON" => SearchParam::On(self.date_arg(&token)?),
+            "OR" => SearchParam::Or(self.key_arg(&token)?, self.key_arg(&token)?),
+            "RECENT" => SearchParam::Recent,
+            "SEEN" => SearchParam::Seen,
+            "SENTBEFORE" => SearchParam::SentBefore(self.date_arg(&token)?),
+            "SENTON" => SearchParam::SentOn(self.date_arg(&token)?),
+            "SENTSINCE" => SearchParam::SentSince(self.date_arg(&token)?),
+            "SINCE" => SearchParam::Since(self.date_arg(&token)?),
+            "SMALLER" => SearchParam::Smaller(self.size_arg(&token)?),
+            "SUBJECT" => SearchParam::Subject(self.string_arg(&token)?),
+            "TEXT" => SearchParam::Text(self.string_arg(&token)?),
+            "TO" => SearchParam::To(self.string_arg(&token)?),
+            "UID" => SearchParam::Uid(self.parsed_arg::<SeqSet>("sequence set", &token)?),
+            "UNANSWERED" => SearchParam::Unanswered,
+            "UNDELETED" => SearchParam::Undeleted,
+            "UNDRAFT" => SearchParam::Undraft,
+            "UNFLAGGED" => SearchParam::Unflagged,
+            "UNKEYWORD" => SearchParam::Unkeyword(self.string_arg(&token)?),
+            "UNSEEN" => SearchParam::Unseen,
+            "SAVEDBEFORE" => SearchParam::SavedBefore(self.date_arg(&token)?),
+            "SAVEDON" => SearchParam::SavedOn(self.date_arg(&token)?),
+            "SAVEDSINCE" => SearchParam::SavedSince(self.date_arg(&token)?),
+            _ => {
+                if let Ok(set) = token.value.parse::<SeqSet>() {
+                    SearchParam::SequenceSet(set)
+                } else {
+                    return Err(
+                        self.error(format!("unknown search key '{}'", token.value), token.pos)
+                    );
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from(value: &str) -> Box<SearchParam> {
+        Box::new(SearchParam::From(value.to_owned()))
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let params =
+            parse_query("mailbox INBOX seen since 2022-01-01 OR from alice from bob NOT deleted")
+                .unwrap();
+        assert_eq!(
+            params,
+            vec![
+                SearchParam::Mailbox("INBOX".to_owned()),
+                SearchParam::Seen,
+                SearchParam::Since(DateSpec::from_ymd(2022, 1, 1).unwrap()),
+                SearchParam::Or(from("alice"), from("bob")),
+                SearchParam::Not(Box::new(SearchParam::Deleted)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_nested() {
+        let params = parse_query(
+            r#"mailbox "Sent Items" NOT (OR from alice from "bob \"b\"" larger 1024) 1:10,*"#,
+        )
+        .unwrap();
+        let mut set = SeqSet::new(crate::doveadm::SeqElement::Range(1, 10));
+        set.add(crate::doveadm::SeqElement::Last);
+        assert_eq!(
+            params,
+            vec![
+                SearchParam::Mailbox("Sent Items".to_owned()),
+                SearchParam::Not(Box::new(SearchParam::Group(vec![
+                    SearchParam::Or(from("alice"), from("bob \"b\"")),
+                    SearchParam::Larger(1024),
+                ]))),
+                SearchParam::SequenceSet(set),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = parse_query("seen since yesterday").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "search query: invalid date 'yesterday' after 'since' at position 12"
+        );
+        let err = parse_query("seen frobnicate").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "search query: unknown search key 'frobnicate' at position 6"
+        );
+        let err = parse_query("OR seen").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "search query: expected a search key after 'OR' at position 8"
+        );
+        assert!(parse_query("(seen").is_err());
+        assert!(parse_query("seen)").is_err());
+        assert!(parse_query("from \"alice").is_err());
+        assert!(parse_query("  ").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let query = "mailbox \"Sent Items\" OR (seen flagged) NOT header X-Spam \"\" \
+                     uid 1,5:10 savedsince 2022-01-31 larger 100 subject \"\"";
+        for param in parse_query(query).unwrap() {
+            let args = param.to_params();
+            assert_eq!(parse_query_args(&args).unwrap(), vec![param]);
+        }
+    }
+}
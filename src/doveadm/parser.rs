@@ -1,30 +1,51 @@
 use crate::doveadm::params::ImapField;
-use crate::doveadm::Reader;
+use crate::doveadm::{Reader, FORM_FEED, TAB};
+use crate::mail::{Address, BodyStructure, Envelope, Header, MimePart};
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset};
 use log::debug;
-use regex::Regex;
+use regex::bytes::Regex;
+use std::fmt::{Display, Formatter};
 
+const TAB_ESCAPE: u8 = 0x1;
+
+mod body_parser;
+pub use body_parser::BodyParser;
+mod date_parser;
+pub use date_parser::DateParser;
 mod flags_parser;
 pub use flags_parser::FlagsParser;
 mod generic_parser;
 pub use generic_parser::GenericParser;
+mod hdr_field_parser;
+pub use hdr_field_parser::HdrFieldParser;
 mod hdr_parser;
 pub use hdr_parser::HdrParser;
+mod imap_parser;
+pub use imap_parser::ImapParser;
+mod number_parser;
+pub use number_parser::NumberParser;
 
 #[derive(Debug)]
 pub struct FetchRecord(Vec<FetchFieldRes>);
 
 impl FetchRecord {
-    pub fn parse(
-        parsers: &Vec<Box<dyn Parser>>,
-        reader: &mut Reader,
-    ) -> Result<Option<FetchRecord>> {
+    pub fn parse(parsers: &[Box<dyn Parser>], reader: &mut Reader) -> Result<Option<FetchRecord>> {
         debug!("FetchRecord::parse: started");
         let mut res: Vec<FetchFieldRes> = Vec::new();
         let mut parsers = parsers.iter();
-        let mut parser = parsers.next().expect("unexpected empty parser list");
+        let parser = parsers.next().expect("unexpected empty parser list");
         let mut next_parser = parsers.next();
 
+        // records are separated by a line containing a form feed
+        if let Some(line) = reader.next_line()? {
+            if !line.ends_with(&[FORM_FEED]) {
+                reader.unconsume();
+            }
+        } else {
+            return Ok(None);
+        }
+
         if let Some(curr_res) = parser
             .parse_first_field(reader, next_parser.map(|parser| parser.get_first_line_re()))?
         {
@@ -44,21 +65,431 @@ impl FetchRecord {
 
         Ok(Some(FetchRecord(res)))
     }
+
+    /// Parse a record from the output of the tab formatter: a line of field names followed by one
+    /// line per record with tab separated values
+    pub fn parse_tab(
+        fields: &[ImapField],
+        parsers: &[Box<dyn Parser>],
+        reader: &mut Reader,
+    ) -> Result<Option<FetchRecord>> {
+        if reader.line_count() == 0 {
+            // the first line contains the field names
+            if let Some(line) = reader.next_line()? {
+                let names: Vec<&[u8]> = line.split(|ch| *ch == TAB).collect();
+                if names.len() != fields.len()
+                    || names
+                        .iter()
+                        .zip(fields.iter())
+                        .any(|(name, field)| *name != field.to_string().as_bytes())
+                {
+                    return Err(anyhow!(
+                        "FetchRecord::parse_tab: unexpected header line: '{}'",
+                        reader.buffer_lossy()
+                    ));
+                }
+            } else {
+                return Ok(None);
+            }
+        }
+
+        if let Some(line) = reader.next_line()? {
+            let values: Vec<&[u8]> = line.split(|ch| *ch == TAB).collect();
+            if values.len() != parsers.len() {
+                return Err(anyhow!(
+                    "FetchRecord::parse_tab: expected {} values, found {} in line {}",
+                    parsers.len(),
+                    values.len(),
+                    reader.line_count()
+                ));
+            }
+            let res = parsers
+                .iter()
+                .zip(values)
+                .map(|(parser, value)| parser.parse_value(&tab_unescape(value)))
+                .collect::<Result<Vec<FetchFieldRes>>>()
+                .with_context(|| format!("in line {}", reader.line_count()))?;
+            Ok(Some(FetchRecord(res)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parse a record from the output of the flow formatter: one line per record containing
+    /// space separated name=value pairs in the order of the fields
+    pub fn parse_flow(
+        fields: &[ImapField],
+        parsers: &[Box<dyn Parser>],
+        reader: &mut Reader,
+    ) -> Result<Option<FetchRecord>> {
+        if let Some(line) = reader.next_line()? {
+            let res = parse_flow_line(fields, parsers, line);
+            Ok(Some(FetchRecord(res.with_context(|| {
+                format!("FetchRecord::parse_flow: in line {}", reader.line_count())
+            })?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn fields(&self) -> &[FetchFieldRes] {
+        &self.0
+    }
+
+    /// The result for the given field, if it was fetched
+    pub fn field(&self, field: &ImapField) -> Option<&FetchFieldRes> {
+        self.0.iter().find(|res| res.field() == field)
+    }
+
+    /// The value of a single line generic field
+    pub fn value(&self, field: &ImapField) -> Option<&str> {
+        match self.field(field)? {
+            FetchFieldRes::Generic((_, FieldType::SingleLine(value))) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn flags(&self) -> Option<&[String]> {
+        match self.field(&ImapField::Flags)? {
+            FetchFieldRes::Flags(flags) => Some(flags.as_slice()),
+            _ => None,
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags()
+            .is_some_and(|flags| flags.iter().any(|curr| curr.eq_ignore_ascii_case(flag)))
+    }
+
+    /// All headers in the order they were fetched
+    pub fn headers(&self) -> Option<&[Header]> {
+        match self.field(&ImapField::Hdr)? {
+            FetchFieldRes::Hdr(headers) => Some(headers.as_slice()),
+            _ => None,
+        }
+    }
+
+    // headers named name from hdr or, if that was not fetched, from hdr.<name>
+    fn find_headers(&self, name: &str) -> Vec<&Header> {
+        let headers = match self.headers() {
+            Some(headers) => headers,
+            None => self
+                .0
+                .iter()
+                .find_map(|res| match res {
+                    FetchFieldRes::HdrField((ImapField::HdrField(hdr_name), headers))
+                        if hdr_name.eq_ignore_ascii_case(name) =>
+                    {
+                        Some(headers.as_slice())
+                    }
+                    _ => None,
+                })
+                .unwrap_or_default(),
+        };
+        headers
+            .iter()
+            .filter(|header| header.name.eq_ignore_ascii_case(name))
+            .collect()
+    }
+
+    /// The decoded value of the first header named name, the lookup is case insensitive.
+    /// Headers are looked up in hdr or, if that was not fetched, in hdr.<name>
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.find_headers(name)
+            .first()
+            .map(|header| header.value.as_str())
+    }
+
+    /// The raw value of the first header named name
+    pub fn header_raw(&self, name: &str) -> Option<&str> {
+        self.find_headers(name)
+            .first()
+            .map(|header| header.raw.as_str())
+    }
+
+    /// The decoded values of all headers named name, the lookup is case insensitive
+    pub fn headers_all(&self, name: &str) -> Vec<&str> {
+        self.find_headers(name)
+            .into_iter()
+            .map(|header| header.value.as_str())
+            .collect()
+    }
+
+    /// The addresses of all headers named name parsed as address lists, eg. 'from' or 'to'
+    pub fn addresses(&self, name: &str) -> Result<Vec<Address>> {
+        let mut res = Vec::new();
+        for header in self.find_headers(name) {
+            res.append(
+                &mut header
+                    .addresses()
+                    .with_context(|| format!("failed to parse {} header", header.name))?,
+            );
+        }
+        Ok(res)
+    }
+
+    /// The raw content of body, text or text.utf8
+    pub fn content(&self, field: &ImapField) -> Option<&[u8]> {
+        match self.field(field)? {
+            FetchFieldRes::Body((_, content)) => Some(content.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// The MIME part tree of the message, parsed from text or text.utf8 if fetched, or from body
+    /// and the fetched headers otherwise
+    pub fn mime(&self) -> Option<MimePart> {
+        if let Some(text) = self
+            .content(&ImapField::Text)
+            .or_else(|| self.content(&ImapField::TextUtf8))
+        {
+            Some(MimePart::parse(text))
+        } else {
+            let body = self.content(&ImapField::Body)?;
+            let headers = match self.headers() {
+                Some(headers) => headers.to_vec(),
+                None => self
+                    .0
+                    .iter()
+                    .filter_map(|res| match res {
+                        FetchFieldRes::HdrField((_, headers)) => Some(headers.iter().cloned()),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect(),
+            };
+            Some(MimePart::parse_body(headers, body))
+        }
+    }
+
+    /// The parsed imap.bodystructure, or imap.body if that was fetched instead
+    pub fn bodystructure(&self) -> Option<&BodyStructure> {
+        [ImapField::ImapBodystructure, ImapField::ImapBody]
+            .iter()
+            .find_map(|field| match self.field(field)? {
+                FetchFieldRes::BodyStructure((_, body)) => Some(body.as_ref()),
+                _ => None,
+            })
+    }
+
+    /// The parsed imap.envelope
+    pub fn envelope(&self) -> Option<&Envelope> {
+        match self.field(&ImapField::ImapEnvelope)? {
+            FetchFieldRes::Envelope(envelope) => Some(envelope.as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn guid(&self) -> Option<&str> {
+        self.value(&ImapField::Guid)
+    }
+
+    pub fn mailbox(&self) -> Option<&str> {
+        self.value(&ImapField::Mailbox)
+    }
+
+    pub fn mailbox_guid(&self) -> Option<&str> {
+        self.value(&ImapField::MailboxGuid)
+    }
+
+    /// The value of a numeric field
+    pub fn number(&self, field: &ImapField) -> Option<u64> {
+        match self.field(field)? {
+            FetchFieldRes::Number((_, number)) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn uid(&self) -> Option<u64> {
+        self.number(&ImapField::Uid)
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.number(&ImapField::Seq)
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.value(&ImapField::User)
+    }
+
+    pub fn size_physical(&self) -> Option<u64> {
+        self.number(&ImapField::SizePhysical)
+    }
+
+    pub fn size_virtual(&self) -> Option<u64> {
+        self.number(&ImapField::SizeVirtual)
+    }
+
+    pub fn date(&self, field: &ImapField) -> Option<&DateTime<FixedOffset>> {
+        match self.field(field)? {
+            FetchFieldRes::Date((_, date)) => Some(date),
+            _ => None,
+        }
+    }
+
+    pub fn date_received(&self) -> Option<&DateTime<FixedOffset>> {
+        self.date(&ImapField::DateReceived)
+    }
+
+    pub fn date_saved(&self) -> Option<&DateTime<FixedOffset>> {
+        self.date(&ImapField::DateSaved)
+    }
+
+    pub fn date_sent(&self) -> Option<&DateTime<FixedOffset>> {
+        self.date(&ImapField::DateSent)
+    }
+}
+
+/// Prints one 'field: value' line per field, the values of multi line fields follow on separate
+/// lines. Headers are printed decoded.
+impl Display for FetchRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for res in &self.0 {
+            let field = res.field();
+            match res {
+                FetchFieldRes::Flags(flags) => writeln!(f, "{}: {}", field, flags.join(" "))?,
+                FetchFieldRes::Hdr(headers) | FetchFieldRes::HdrField((_, headers)) => {
+                    writeln!(f, "{}:", field)?;
+                    for header in headers {
+                        writeln!(f, "{}: {}", header.name, header.value)?;
+                    }
+                }
+                FetchFieldRes::Body((_, content)) => {
+                    writeln!(f, "{}:", field)?;
+                    writeln!(f, "{}", String::from_utf8_lossy(content).trim_end())?;
+                }
+                FetchFieldRes::BodyStructure((_, body)) => writeln!(f, "{}: {:?}", field, body)?,
+                FetchFieldRes::Envelope(envelope) => writeln!(f, "{}: {:?}", field, envelope)?,
+                FetchFieldRes::Date((_, date)) => {
+                    writeln!(f, "{}: {}", field, date.format("%Y-%m-%d %H:%M:%S %z"))?
+                }
+                FetchFieldRes::Number((_, number)) => writeln!(f, "{}: {}", field, number)?,
+                FetchFieldRes::Generic((_, FieldType::SingleLine(value))) => {
+                    writeln!(f, "{}: {}", field, value)?
+                }
+                FetchFieldRes::Generic((_, FieldType::MultiLine(lines))) => {
+                    writeln!(f, "{}:", field)?;
+                    for (name, value) in lines {
+                        writeln!(f, "{}: {}", name, value)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
-enum FieldType {
+pub enum FieldType {
     MultiLine(Vec<(String, String)>),
-    SingleLine(Vec<String>),
+    SingleLine(String),
 }
 
 #[derive(Debug)]
 pub enum FetchFieldRes {
     Flags(Vec<String>),
-    Hdr(Vec<(String, String)>),
+    Hdr(Vec<Header>),
+    HdrField((ImapField, Vec<Header>)),
+    // message content, raw
+    Body((ImapField, Vec<u8>)),
+    BodyStructure((ImapField, Box<BodyStructure>)),
+    Envelope(Box<Envelope>),
+    Date((ImapField, DateTime<FixedOffset>)),
+    Number((ImapField, u64)),
     Generic((ImapField, FieldType)),
 }
 
+impl FetchFieldRes {
+    /// The field this result was parsed from
+    pub fn field(&self) -> &ImapField {
+        match self {
+            FetchFieldRes::Flags(_) => &ImapField::Flags,
+            FetchFieldRes::Hdr(_) => &ImapField::Hdr,
+            FetchFieldRes::HdrField((field, _)) => field,
+            FetchFieldRes::Body((field, _)) => field,
+            FetchFieldRes::BodyStructure((field, _)) => field,
+            FetchFieldRes::Envelope(_) => &ImapField::ImapEnvelope,
+            FetchFieldRes::Date((field, _)) => field,
+            FetchFieldRes::Number((field, _)) => field,
+            FetchFieldRes::Generic((field, _)) => field,
+        }
+    }
+}
+
+/// Create the parser for the given field
+pub fn new_parser(field: &ImapField) -> Result<Box<dyn Parser>> {
+    Ok(match field {
+        ImapField::Flags => Box::new(FlagsParser::new()?),
+        ImapField::Hdr => Box::new(HdrParser::new()?),
+        ImapField::HdrField(_) => Box::new(HdrFieldParser::new(field)?),
+        ImapField::Body | ImapField::Text | ImapField::TextUtf8 => {
+            Box::new(BodyParser::new(field)?)
+        }
+        ImapField::ImapBody | ImapField::ImapBodystructure | ImapField::ImapEnvelope => {
+            Box::new(ImapParser::new(field)?)
+        }
+        field if field.is_date() => Box::new(DateParser::new(field)?),
+        field if field.is_numeric() => Box::new(NumberParser::new(field)?),
+        _ => Box::new(GenericParser::new(field)?),
+    })
+}
+
+fn parse_flow_line(
+    fields: &[ImapField],
+    parsers: &[Box<dyn Parser>],
+    line: &[u8],
+) -> Result<Vec<FetchFieldRes>> {
+    let mut res: Vec<FetchFieldRes> = Vec::new();
+    let mut rest = line;
+    for (idx, (field, parser)) in fields.iter().zip(parsers.iter()).enumerate() {
+        let name = format!("{}=", field);
+        rest = rest
+            .strip_prefix(name.as_bytes())
+            .ok_or_else(|| anyhow!("expected {}", field))?;
+        // values may contain spaces, so a value ends where the next field starts
+        let value = if let Some(next) = fields.get(idx + 1) {
+            let next_name = format!(" {}=", next);
+            let end = find_bytes(rest, next_name.as_bytes())
+                .ok_or_else(|| anyhow!("expected {}", next))?;
+            let value = &rest[..end];
+            rest = &rest[end + 1..];
+            value
+        } else {
+            rest
+        };
+        res.push(parser.parse_value(value)?);
+    }
+    Ok(res)
+}
+
+// dovecot's tab escaping: \x01 is the escape character, followed by '1' for itself,
+// 't' for tab, 'r' for carriage return and 'n' for line feed
+fn tab_unescape(value: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(value.len());
+    let mut bytes = value.iter();
+    while let Some(ch) = bytes.next() {
+        if *ch == TAB_ESCAPE {
+            match bytes.next() {
+                Some(b'1') => res.push(TAB_ESCAPE),
+                Some(b't') => res.push(TAB),
+                Some(b'r') => res.push(b'\r'),
+                Some(b'n') => res.push(b'\n'),
+                Some(ch) => res.push(*ch),
+                None => (),
+            }
+        } else {
+            res.push(*ch);
+        }
+    }
+    res
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
 pub trait Parser {
     // used by some preceding parsers to find the end of record (start of next)
     fn get_first_line_re(&self) -> &Regex;
@@ -83,4 +514,81 @@ pub trait Parser {
             Err(anyhow!("unexpected empty subsequent field"))
         }
     }
+    // parse a field from a value printed by the tab or flow formatter
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        Err(anyhow!(
+            "unable to parse a multi line field from single line value '{}'",
+            String::from_utf8_lossy(value)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn parse_all(fields: &[ImapField], input: &'static [u8]) -> Result<Vec<FetchRecord>> {
+        let parsers = fields
+            .iter()
+            .map(new_parser)
+            .collect::<Result<Vec<Box<dyn Parser>>>>()?;
+        let mut reader = Reader::new(Box::new(Cursor::new(input)));
+        let mut res = Vec::new();
+        while let Some(record) = FetchRecord::parse(&parsers, &mut reader)? {
+            res.push(record);
+        }
+        Ok(res)
+    }
+
+    #[test]
+    fn test_accessors() {
+        let records = parse_all(
+            &[ImapField::Flags, ImapField::Mailbox, ImapField::DateSaved, ImapField::Hdr],
+            b"flags: \\Seen \\Answered\nmailbox: Sent Items\ndate.saved: 2022-08-15 10:23:45\nhdr:\nReceived: from a\nsubject: Hello\nReceived: from b\n\n",
+        )
+        .unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(
+            record.flags().unwrap(),
+            &["\\Seen".to_owned(), "\\Answered".to_owned()]
+        );
+        assert!(record.has_flag("\\seen"));
+        assert_eq!(record.mailbox(), Some("Sent Items"));
+        assert_eq!(record.header("Subject"), Some("Hello"));
+        assert_eq!(record.headers_all("received"), vec!["from a", "from b"]);
+        assert_eq!(record.guid(), None);
+        assert!(record.date_saved().is_some());
+        assert!(record.date_sent().is_none());
+    }
+
+    #[test]
+    fn test_hdr_values() {
+        let records = parse_all(
+            &[ImapField::Hdr],
+            b"hdr:\nFrom: a@b.c\nSubject:\nX-Foo:bar\nX-Folded: one\n\ttwo\n\n",
+        )
+        .unwrap();
+        let record = &records[0];
+        assert_eq!(record.header("From"), Some("a@b.c"));
+        assert_eq!(record.header("Subject"), Some(""));
+        assert_eq!(record.header("X-Foo"), Some("bar"));
+        assert_eq!(record.header_raw("X-Folded"), Some("one\n\ttwo"));
+
+        // a line that neither starts a field nor continues one
+        assert!(parse_all(&[ImapField::Hdr], b"hdr:\nFrom: a@b.c\nno colon\n\n").is_err());
+    }
+
+    #[test]
+    fn test_8bit() {
+        let records = parse_all(
+            &[ImapField::Mailbox, ImapField::Hdr],
+            b"mailbox: Gr\xfcn\nhdr:\nFrom: J\xf6rg <j@example.de>\nSubject: \xff\n\n",
+        )
+        .unwrap();
+        assert_eq!(records[0].mailbox(), Some("Gr\u{fffd}n"));
+        assert_eq!(records[0].header("from"), Some("J\u{f6}rg <j@example.de>"));
+        assert_eq!(records[0].header("subject"), Some("\u{ff}"));
+    }
 }
@@ -0,0 +1,66 @@
+use crate::doveadm::params::ImapField;
+use crate::doveadm::parser::{FetchFieldRes, Parser};
+use crate::doveadm::{Reader, FORM_FEED, LINE_FEED};
+use anyhow::{anyhow, Context, Result};
+use regex::bytes::Regex;
+
+/// Parses message content - body, text and text.utf8 - into raw bytes, MIME parsing is done on
+/// demand by FetchRecord::mime
+pub struct BodyParser {
+    field_type: ImapField,
+    first_line_re: Regex,
+}
+
+impl BodyParser {
+    pub fn new(field: &ImapField) -> Result<BodyParser> {
+        let re_str = format!(r"(?-u)^{}:(\s(.*))?$", regex::escape(&field.to_string()));
+        Ok(BodyParser {
+            field_type: field.clone(),
+            first_line_re: Regex::new(re_str.as_str())
+                .with_context(|| format!("failed to create regex from '{}'", re_str))?,
+        })
+    }
+}
+
+impl Parser for BodyParser {
+    fn get_first_line_re(&self) -> &Regex {
+        &self.first_line_re
+    }
+
+    fn parse_first_field(
+        &self,
+        reader: &mut Reader,
+        next_re: Option<&Regex>,
+    ) -> Result<Option<FetchFieldRes>> {
+        if let Some(line) = reader.next_line()? {
+            let mut res: Vec<u8> = Vec::new();
+            if let Some(captures) = self.first_line_re.captures(line) {
+                if let Some(value) = captures.get(2).filter(|value| !value.is_empty()) {
+                    res.extend_from_slice(value.as_bytes());
+                    res.push(LINE_FEED);
+                }
+            } else {
+                return Err(anyhow!(
+                    "BodyParser::parse_first_field: no match for {} parser in line {}: '{}'",
+                    self.field_type,
+                    reader.line_count(),
+                    reader.buffer_lossy()
+                ));
+            }
+
+            let next_field_re = next_re.unwrap_or(&self.first_line_re);
+            while let Some(line) = reader.next_line()? {
+                if line.ends_with(&[FORM_FEED]) || next_field_re.is_match(line) {
+                    reader.unconsume();
+                    break;
+                }
+                res.extend_from_slice(line);
+                res.push(LINE_FEED);
+            }
+            // end of field or EOI - EOI is a valid end for the last field of the last record
+            Ok(Some(FetchFieldRes::Body((self.field_type.clone(), res))))
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::doveadm::params::ImapField;
+use crate::doveadm::parser::{FetchFieldRes, Parser};
+use crate::doveadm::Reader;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone};
+use regex::bytes::Regex;
+
+// doveadm prints all dates as local time in this format
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Parses date.received, date.saved and date.sent and their unixtime variants
+///
+/// date.received and date.saved are printed as local time, date.sent is printed as local time
+/// followed by the offset of the senders timezone, eg. '2022-08-15 10:23:45 (+0200)'.
+/// The unixtime variants are printed as seconds since the epoch and converted to local time.
+pub struct DateParser {
+    field_type: ImapField,
+    first_line_re: Regex,
+    value_re: regex::Regex,
+}
+
+impl DateParser {
+    pub fn new(field: &ImapField) -> Result<DateParser> {
+        let value_re_str = match field {
+            ImapField::DateReceived | ImapField::DateSaved => {
+                r"^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})$"
+            }
+            ImapField::DateSent => {
+                r"^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}) \(([+-])(\d{2})(\d{2})\)$"
+            }
+            ImapField::DateReceivedUnixtime
+            | ImapField::DateSavedUnixtime
+            | ImapField::DateSentUnixtime => r"^(-?\d+)$",
+            _ => return Err(anyhow!("DateParser::new: {} is not a date field", field)),
+        };
+        let re_str = format!(r"(?-u)^{}:\s(.*)$", regex::escape(&field.to_string()));
+        Ok(DateParser {
+            field_type: field.clone(),
+            first_line_re: Regex::new(re_str.as_str())
+                .with_context(|| format!("failed to create regex from '{}'", re_str))?,
+            value_re: regex::Regex::new(value_re_str)
+                .with_context(|| format!("failed to create regex from '{}'", value_re_str))?,
+        })
+    }
+
+    fn parse_date(&self, value: &str) -> Option<DateTime<FixedOffset>> {
+        let captures = self.value_re.captures(value)?;
+        if matches!(
+            self.field_type,
+            ImapField::DateReceivedUnixtime
+                | ImapField::DateSavedUnixtime
+                | ImapField::DateSentUnixtime
+        ) {
+            let timestamp = captures[1].parse::<i64>().ok()?;
+            return Some(Local.timestamp_opt(timestamp, 0).single()?.fixed_offset());
+        }
+        let local = NaiveDateTime::parse_from_str(&captures[1], DATE_FORMAT).ok()?;
+        let local = Local.from_local_datetime(&local).earliest()?;
+        if let Some(sign) = captures.get(2) {
+            let offset =
+                captures[3].parse::<i32>().ok()? * 3600 + captures[4].parse::<i32>().ok()? * 60;
+            let offset = if sign.as_str() == "-" {
+                FixedOffset::west_opt(offset)?
+            } else {
+                FixedOffset::east_opt(offset)?
+            };
+            Some(local.with_timezone(&offset))
+        } else {
+            Some(local.fixed_offset())
+        }
+    }
+}
+
+impl Parser for DateParser {
+    fn get_first_line_re(&self) -> &Regex {
+        &self.first_line_re
+    }
+
+    fn parse_first_field(
+        &self,
+        reader: &mut Reader,
+        _next_re: Option<&Regex>,
+    ) -> Result<Option<FetchFieldRes>> {
+        // this is a one-liner, so next_re is not needed
+        if let Some(line) = reader.next_line()? {
+            if let Some(captures) = self.first_line_re.captures(line) {
+                let res = self.parse_value(&captures[1]);
+                res.with_context(|| format!("in line {}", reader.line_count()))
+                    .map(Some)
+            } else {
+                Err(anyhow!(
+                    "DateParser::parse_first_field: no match for {} parser in line {}: '{}'",
+                    self.field_type,
+                    reader.line_count(),
+                    reader.buffer_lossy()
+                ))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        let value = String::from_utf8_lossy(value);
+        if let Some(date) = self.parse_date(&value) {
+            Ok(FetchFieldRes::Date((self.field_type.clone(), date)))
+        } else {
+            Err(anyhow!(
+                "DateParser::parse_value: invalid {}: '{}'",
+                self.field_type,
+                value
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(value: &str) -> DateTime<Local> {
+        Local
+            .from_local_datetime(&NaiveDateTime::parse_from_str(value, DATE_FORMAT).unwrap())
+            .earliest()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_date_received() {
+        let parser = DateParser::new(&ImapField::DateReceived).unwrap();
+        let date = parser.parse_date("2022-08-15 10:23:45").unwrap();
+        assert_eq!(date, local("2022-08-15 10:23:45"));
+        assert!(parser.parse_date("2022-08-15").is_none());
+    }
+
+    #[test]
+    fn test_date_sent() {
+        let parser = DateParser::new(&ImapField::DateSent).unwrap();
+        let date = parser.parse_date("2022-08-15 10:23:45 (-0430)").unwrap();
+        assert_eq!(date, local("2022-08-15 10:23:45"));
+        assert_eq!(
+            date.offset(),
+            &FixedOffset::west_opt(4 * 3600 + 30 * 60).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unixtime() {
+        let parser = DateParser::new(&ImapField::DateSavedUnixtime).unwrap();
+        let date = parser.parse_date("1660551825").unwrap();
+        assert_eq!(date.timestamp(), 1660551825);
+        assert!(parser.parse_date("2022-08-15 10:23:45").is_none());
+    }
+}
@@ -2,7 +2,7 @@ use crate::doveadm::params::ImapField;
 use crate::doveadm::parser::{FetchFieldRes, Parser};
 use crate::doveadm::Reader;
 use anyhow::{anyhow, Context, Result};
-use regex::Regex;
+use regex::bytes::Regex;
 
 pub struct FlagsParser {
     first_line_re: Regex,
@@ -10,7 +10,10 @@ pub struct FlagsParser {
 
 impl FlagsParser {
     pub fn new() -> Result<FlagsParser> {
-        let re_str = format!(r"^{}:\s+(.*)$", ImapField::Flags.to_string());
+        let re_str = format!(
+            r"(?-u)^{}:\s+(.*)$",
+            regex::escape(&ImapField::Flags.to_string())
+        );
         Ok(FlagsParser {
             first_line_re: Regex::new(re_str.as_str())
                 .with_context(|| format!("failed to create regex from '{}'", re_str))?,
@@ -32,13 +35,7 @@ impl Parser for FlagsParser {
         if let Some(line) = reader.next_line()? {
             if let Some(captures) = self.first_line_re.captures(line) {
                 if let Some(flags) = captures.get(1) {
-                    Ok(Some(FetchFieldRes::Flags(
-                        flags
-                            .as_str()
-                            .split_whitespace()
-                            .map(|part| part.to_owned())
-                            .collect(),
-                    )))
+                    Ok(Some(self.parse_value(flags.as_bytes())?))
                 } else {
                     Err(anyhow!("Flags parser matched but no caption")) //
                 }
@@ -49,4 +46,13 @@ impl Parser for FlagsParser {
             Ok(None)
         }
     }
+
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        Ok(FetchFieldRes::Flags(
+            String::from_utf8_lossy(value)
+                .split_whitespace()
+                .map(|part| part.to_owned())
+                .collect(),
+        ))
+    }
 }
@@ -1,8 +1,8 @@
 use crate::doveadm::params::ImapField;
 use crate::doveadm::parser::{FetchFieldRes, FieldType, Parser};
-use crate::doveadm::{Reader, FORM_FEED, LINE_FEED};
+use crate::doveadm::{Reader, FORM_FEED};
 use anyhow::{anyhow, Context, Result};
-use regex::Regex;
+use regex::bytes::Regex;
 
 pub struct GenericParser {
     field_type: ImapField,
@@ -12,8 +12,8 @@ pub struct GenericParser {
 
 impl GenericParser {
     pub fn new(field: &ImapField) -> Result<GenericParser> {
-        let re_str = format!(r"^{}:(\s(.*))?$", field.to_string());
-        let subseq_re_str = r"^([\S^:]+):\s(.*)$";
+        let re_str = format!(r"(?-u)^{}:(\s(.*))?$", regex::escape(&field.to_string()));
+        let subseq_re_str = r"(?-u)^([\S^:]+):\s(.*)$";
         Ok(GenericParser {
             field_type: field.clone(),
             first_line_re: Regex::new(re_str.as_str()).with_context(|| {
@@ -39,22 +39,9 @@ impl Parser for GenericParser {
     ) -> Result<Option<FetchFieldRes>> {
         if let Some(line) = reader.next_line()? {
             if let Some(captures) = self.first_line_re.captures(line) {
-                if captures.len() > 1 {
+                if let Some(value) = captures.get(2) {
                     // single line field
-                    Ok(Some(FetchFieldRes::Generic((
-                        self.field_type.clone(),
-                        FieldType::SingleLine(
-                            captures
-                                .get(1)
-                                .expect(
-                                    "GenericParser::parse_first_field: unexpected empty capture",
-                                )
-                                .as_str()
-                                .split_whitespace()
-                                .map(|part| part.to_owned())
-                                .collect(),
-                        ),
-                    ))))
+                    Ok(Some(self.parse_value(value.as_bytes())?))
                 } else {
                     // multi line or empty
                     let next_field_re = if let Some(next_re) = next_re {
@@ -64,65 +51,46 @@ impl Parser for GenericParser {
                     };
                     let mut res: Vec<(String, String)> = Vec::new();
                     while let Some(line) = reader.next_line()? {
-                        let line = line.trim_end_matches(LINE_FEED);
-                        if line.ends_with(FORM_FEED) || next_field_re.is_match(line) {
+                        if line.ends_with(&[FORM_FEED]) || next_field_re.is_match(line) {
                             reader.unconsume();
-                            if res.len() > 0 {
-                                return Ok(Some(FetchFieldRes::Generic((
-                                    self.field_type.clone(),
-                                    FieldType::MultiLine(res),
-                                ))));
-                            } else {
-                                return Err(anyhow!(
-                                    "GenericParser::parse_first_field: unexpected empty field"
-                                ));
-                            }
+                            break;
+                        } else if let Some(captures) = self.subseq_line_re.captures(line) {
+                            res.push((
+                                String::from_utf8_lossy(&captures[1]).into_owned(),
+                                String::from_utf8_lossy(&captures[2]).into_owned(),
+                            ));
+                        } else if let Some(last_res) = res.last_mut() {
+                            last_res.1.push('\n');
+                            last_res.1.push_str(&String::from_utf8_lossy(line));
                         } else {
-                            if let Some(captures) = self.subseq_line_re.captures(line) {
-                                res.push((
-                                    captures
-                                        .get(1)
-                                        .expect(
-                                            format!("GenericParser::parse_first_field: unexpected empty Hdr name in line '{}'", line)
-                                                .as_str(),
-                                        )
-                                        .as_str()
-                                        .to_owned(),
-                                    captures
-                                        .get(2)
-                                        .expect(
-                                            format!("GenericParser::parse_first_field: unexpected empty Hdr value in line '{}'", line)
-                                                .as_str(),
-                                        )
-                                        .as_str()
-                                        .to_owned(),
-                                ));
-                            } else {
-                                if let Some(last_res) = res.last_mut() {
-                                    last_res.1.push('\n');
-                                    last_res.1.push_str(line);
-                                } else {
-                                    return Err(anyhow!(
-                                    "GenericParser::parse_first_field: hdr regex failed to match in line '{}'",
-                                    line
-                                ));
-                                }
-                            }
+                            return Err(anyhow!(
+                                "GenericParser::parse_first_field: hdr regex failed to match in line {}: '{}'",
+                                reader.line_count(),
+                                reader.buffer_lossy()
+                            ));
                         }
                     }
-                    // no next line
-                    // TODO: really ? or rather return what we have got
-                    Err(anyhow!(
-                        "GenericParser::parse_first_field: unexpected EOI in field"
-                    ))
+                    // end of field or EOI - EOI is a valid end for the last field of the last record
+                    Ok(Some(FetchFieldRes::Generic((
+                        self.field_type.clone(),
+                        FieldType::MultiLine(res),
+                    ))))
                 }
             } else {
                 Err(anyhow!(
-                    "GenericParser::parse_first_field: Hdr parser failed to match first line"
+                    "GenericParser::parse_first_field: {} parser failed to match first line",
+                    self.field_type
                 ))
             }
         } else {
             Ok(None)
         }
     }
+
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        Ok(FetchFieldRes::Generic((
+            self.field_type.clone(),
+            FieldType::SingleLine(String::from_utf8_lossy(value).into_owned()),
+        )))
+    }
 }
@@ -0,0 +1,99 @@
+use crate::doveadm::params::ImapField;
+use crate::doveadm::parser::{FetchFieldRes, Parser};
+use crate::doveadm::{Reader, FORM_FEED};
+use crate::mail::{decode_8bit, Header};
+use anyhow::{anyhow, Context, Result};
+use regex::bytes::Regex;
+
+/// Parses a single header field, hdr.<name>
+///
+/// doveadm prints all values of the header separated by line feeds, lines starting with
+/// whitespace continue a folded value
+pub struct HdrFieldParser {
+    field_type: ImapField,
+    name: String,
+    first_line_re: Regex,
+}
+
+impl HdrFieldParser {
+    pub fn new(field: &ImapField) -> Result<HdrFieldParser> {
+        let name = match field {
+            ImapField::HdrField(name) => name.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "HdrFieldParser::new: {} is not a header field",
+                    field
+                ))
+            }
+        };
+        let re_str = format!(r"(?-u)^{}:(\s(.*))?$", regex::escape(&field.to_string()));
+        Ok(HdrFieldParser {
+            field_type: field.clone(),
+            name,
+            first_line_re: Regex::new(re_str.as_str())
+                .with_context(|| format!("failed to create regex from '{}'", re_str))?,
+        })
+    }
+}
+
+impl Parser for HdrFieldParser {
+    fn get_first_line_re(&self) -> &Regex {
+        &self.first_line_re
+    }
+
+    fn parse_first_field(
+        &self,
+        reader: &mut Reader,
+        next_re: Option<&Regex>,
+    ) -> Result<Option<FetchFieldRes>> {
+        if let Some(line) = reader.next_line()? {
+            // lines are decoded one by one, the lines of a folded value may differ in charset
+            let mut values: Vec<String> = Vec::new();
+            if let Some(captures) = self.first_line_re.captures(line) {
+                if let Some(value) = captures.get(2) {
+                    values.push(decode_8bit(value.as_bytes()));
+                }
+            } else {
+                return Err(anyhow!(
+                    "HdrFieldParser::parse_first_field: no match for {} parser in line {}: '{}'",
+                    self.field_type,
+                    reader.line_count(),
+                    reader.buffer_lossy()
+                ));
+            }
+
+            let next_field_re = next_re.unwrap_or(&self.first_line_re);
+            while let Some(line) = reader.next_line()? {
+                if line.ends_with(&[FORM_FEED]) || next_field_re.is_match(line) {
+                    reader.unconsume();
+                    break;
+                } else if (line.starts_with(b" ") || line.starts_with(b"\t")) && !values.is_empty()
+                {
+                    if let Some(last) = values.last_mut() {
+                        last.push('\n');
+                        last.push_str(&decode_8bit(line));
+                    }
+                } else {
+                    values.push(decode_8bit(line));
+                }
+            }
+
+            Ok(Some(FetchFieldRes::HdrField((
+                self.field_type.clone(),
+                values
+                    .into_iter()
+                    .map(|value| Header::new(self.name.clone(), value))
+                    .collect(),
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        Ok(FetchFieldRes::HdrField((
+            self.field_type.clone(),
+            vec![Header::new(self.name.clone(), decode_8bit(value))],
+        )))
+    }
+}
@@ -1,9 +1,9 @@
 use crate::doveadm::params::ImapField;
-use crate::doveadm::parser::FetchFieldRes::Hdr;
 use crate::doveadm::parser::{FetchFieldRes, Parser};
-use crate::doveadm::{Reader, FORM_FEED, LINE_FEED};
+use crate::doveadm::{Reader, FORM_FEED};
+use crate::mail::{decode_8bit, Header};
 use anyhow::{anyhow, Context, Result};
-use regex::Regex;
+use regex::bytes::Regex;
 
 pub struct HdrParser {
     first_line_re: Regex,
@@ -11,8 +11,9 @@ pub struct HdrParser {
 }
 impl HdrParser {
     pub fn new() -> Result<HdrParser> {
-        let re_str = format!(r"^{}:$", ImapField::Hdr.to_string());
-        let subseq_re_str = r"^([\S^:]+):\s(.*)$";
+        let re_str = format!(r"(?-u)^{}:$", regex::escape(&ImapField::Hdr.to_string()));
+        // field names are printable US-ASCII except colon, RFC 5322 section 2.2
+        let subseq_re_str = r"(?-u)^([!-9;-~]+):[ \t]*(.*)$";
         Ok(HdrParser {
             first_line_re: Regex::new(re_str.as_str())
                 .with_context(|| format!("failed to create regex from '{}'", re_str))?,
@@ -40,59 +41,45 @@ impl Parser for HdrParser {
                 } else {
                     &self.first_line_re
                 };
+                // lines are decoded one by one, the lines of a folded value may differ in charset
                 let mut res: Vec<(String, String)> = Vec::new();
                 while let Some(line) = reader.next_line()? {
-                    let line = line.trim_end_matches(LINE_FEED);
-                    if line.ends_with(FORM_FEED) || next_field_re.is_match(line) {
+                    if line.ends_with(&[FORM_FEED]) || next_field_re.is_match(line) {
                         reader.unconsume();
-                        if res.len() > 0 {
-                            return Ok(Some(FetchFieldRes::Hdr(res)));
-                        } else {
-                            // TODO: or accept an empty field res ?
-                            return Err(anyhow!(
-                                "HdrParser::parse_first_field: unexpected empty field"
-                            ));
-                        }
+                        break;
+                    } else if line.is_empty() {
+                        // the empty line terminating the header block
+                        continue;
+                    } else if let (Some(b' ' | b'\t'), Some(last_res)) =
+                        (line.first(), res.last_mut())
+                    {
+                        // a folded value continues on lines starting with white space
+                        last_res.1.push('\n');
+                        last_res.1.push_str(&decode_8bit(line));
+                    } else if let Some(captures) = self.subseq_line_re.captures(line) {
+                        res.push((
+                            String::from_utf8_lossy(&captures[1]).into_owned(),
+                            decode_8bit(&captures[2]),
+                        ));
                     } else {
-                        if let Some(captures) = self.subseq_line_re.captures(line) {
-                            res.push((
-                                captures
-                                    .get(1)
-                                    .expect(
-                                        format!("HdrParser::parse_first_field: unexpected empty Hdr name in line '{}'", line)
-                                            .as_str(),
-                                    )
-                                    .as_str()
-                                    .to_owned(),
-                                captures
-                                    .get(2)
-                                    .expect(
-                                        format!("HdrParser::parse_first_field: unexpected empty Hdr value in line '{}'", line)
-                                            .as_str(),
-                                    )
-                                    .as_str()
-                                    .to_owned(),
-                            ));
-                        } else {
-                            if let Some(last_res) = res.last_mut() {
-                                last_res.1.push('\n');
-                                last_res.1.push_str(line);
-                            } else {
-                                return Err(anyhow!(
-                                    "HdrParser::parse_first_field: hdr regex failed to match in line '{}'",
-                                    line
-                                ));
-                            }
-                        }
+                        return Err(anyhow!(
+                            "HdrParser::parse_first_field: hdr regex failed to match in line {}: '{}'",
+                            reader.line_count(),
+                            reader.buffer_lossy()
+                        ));
                     }
                 }
+                // end of field or EOI - EOI is a valid end for the last field of the last record
+                Ok(Some(FetchFieldRes::Hdr(
+                    res.into_iter()
+                        .map(|(name, value)| Header::new(name, value))
+                        .collect(),
+                )))
             } else {
-                return Err(anyhow!(
+                Err(anyhow!(
                     "HdrParser::parse_first_field: Hdr parser failed to match first line"
-                ));
+                ))
             }
-            // TODO: really ? or rather return what we have got
-            Err(anyhow!("unexpected EOI in field"))
         } else {
             Ok(None)
         }
@@ -0,0 +1,88 @@
+use crate::doveadm::params::ImapField;
+use crate::doveadm::parser::{FetchFieldRes, Parser};
+use crate::doveadm::{Reader, FORM_FEED, LINE_FEED};
+use crate::mail::{BodyStructure, Envelope};
+use anyhow::{anyhow, Context, Result};
+use regex::bytes::Regex;
+
+/// Parses IMAP structures - imap.body, imap.bodystructure and imap.envelope. Values may continue on subsequent
+/// lines when they contain literals.
+pub struct ImapParser {
+    field_type: ImapField,
+    first_line_re: Regex,
+}
+
+impl ImapParser {
+    pub fn new(field: &ImapField) -> Result<ImapParser> {
+        let re_str = format!(r"(?-u)^{}:(\s(.*))?$", regex::escape(&field.to_string()));
+        Ok(ImapParser {
+            field_type: field.clone(),
+            first_line_re: Regex::new(re_str.as_str())
+                .with_context(|| format!("failed to create regex from '{}'", re_str))?,
+        })
+    }
+}
+
+impl Parser for ImapParser {
+    fn get_first_line_re(&self) -> &Regex {
+        &self.first_line_re
+    }
+
+    fn parse_first_field(
+        &self,
+        reader: &mut Reader,
+        next_re: Option<&Regex>,
+    ) -> Result<Option<FetchFieldRes>> {
+        if let Some(line) = reader.next_line()? {
+            let mut value: Vec<u8> = Vec::new();
+            if let Some(captures) = self.first_line_re.captures(line) {
+                if let Some(first) = captures.get(2) {
+                    value.extend_from_slice(first.as_bytes());
+                }
+            } else {
+                return Err(anyhow!(
+                    "ImapParser::parse_first_field: no match for {} parser in line {}: '{}'",
+                    self.field_type,
+                    reader.line_count(),
+                    reader.buffer_lossy()
+                ));
+            }
+
+            let next_field_re = next_re.unwrap_or(&self.first_line_re);
+            while let Some(line) = reader.next_line()? {
+                if line.ends_with(&[FORM_FEED]) || next_field_re.is_match(line) {
+                    reader.unconsume();
+                    break;
+                }
+                if !value.is_empty() {
+                    value.push(LINE_FEED);
+                }
+                value.extend_from_slice(line);
+            }
+            let line_count = reader.line_count();
+            self.parse_value(&value)
+                .with_context(|| format!("in line {}", line_count))
+                .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        match self.field_type {
+            ImapField::ImapBody | ImapField::ImapBodystructure => {
+                Ok(FetchFieldRes::BodyStructure((
+                    self.field_type.clone(),
+                    Box::new(BodyStructure::parse(value)?),
+                )))
+            }
+            ImapField::ImapEnvelope => {
+                Ok(FetchFieldRes::Envelope(Box::new(Envelope::parse(value)?)))
+            }
+            _ => Err(anyhow!(
+                "ImapParser::parse_value: {} is not an IMAP structure",
+                self.field_type
+            )),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::doveadm::params::ImapField;
+use crate::doveadm::parser::{FetchFieldRes, Parser};
+use crate::doveadm::Reader;
+use anyhow::{anyhow, Context, Result};
+use regex::bytes::Regex;
+
+/// Parses numeric fields like uid, seq and size.physical
+pub struct NumberParser {
+    field_type: ImapField,
+    first_line_re: Regex,
+}
+
+impl NumberParser {
+    pub fn new(field: &ImapField) -> Result<NumberParser> {
+        let re_str = format!(r"(?-u)^{}:\s(.*)$", regex::escape(&field.to_string()));
+        Ok(NumberParser {
+            field_type: field.clone(),
+            first_line_re: Regex::new(re_str.as_str())
+                .with_context(|| format!("failed to create regex from '{}'", re_str))?,
+        })
+    }
+}
+
+impl Parser for NumberParser {
+    fn get_first_line_re(&self) -> &Regex {
+        &self.first_line_re
+    }
+
+    fn parse_first_field(
+        &self,
+        reader: &mut Reader,
+        _next_re: Option<&Regex>,
+    ) -> Result<Option<FetchFieldRes>> {
+        // this is a one-liner, so next_re is not needed
+        if let Some(line) = reader.next_line()? {
+            if let Some(captures) = self.first_line_re.captures(line) {
+                let res = self.parse_value(&captures[1]);
+                res.with_context(|| format!("in line {}", reader.line_count()))
+                    .map(Some)
+            } else {
+                Err(anyhow!(
+                    "NumberParser::parse_first_field: no match for {} parser in line {}: '{}'",
+                    self.field_type,
+                    reader.line_count(),
+                    reader.buffer_lossy()
+                ))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_value(&self, value: &[u8]) -> Result<FetchFieldRes> {
+        let value = String::from_utf8_lossy(value);
+        let number = value.trim().parse::<u64>().with_context(|| {
+            format!(
+                "NumberParser::parse_value: invalid {}: '{}'",
+                self.field_type, value
+            )
+        })?;
+        Ok(FetchFieldRes::Number((self.field_type.clone(), number)))
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{anyhow, Error, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// What DoveadmFetch does when a record can not be parsed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, strum_macros::Display)]
+pub enum Recovery {
+    // fail with the parser error
+    #[default]
+    #[strum(serialize = "abort")]
+    Abort,
+    // skip to the start of the next record and keep going
+    #[strum(serialize = "skip")]
+    Skip,
+}
+
+impl FromStr for Recovery {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "abort" => Ok(Recovery::Abort),
+            "skip" => Ok(Recovery::Skip),
+            _ => Err(anyhow!("invalid recovery mode {}", s)),
+        }
+    }
+}
+
+/// A record that was skipped in Recovery::Skip mode
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedRecord {
+    /// First line of the record in the doveadm output, 1 based
+    pub first_line: usize,
+    /// Last line skipped
+    pub last_line: usize,
+    /// The parser error
+    pub reason: String,
+}
+
+impl Display for SkippedRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "lines {}-{}: {}",
+            self.first_line, self.last_line, self.reason
+        )
+    }
+}
@@ -0,0 +1,458 @@
+use crate::doveadm::{
+    DoveadmError, DoveadmFetch, FetchParams, FetchRecord, Recovery, SkippedRecord, DOVEADM_CMD,
+};
+use anyhow::{anyhow, Error, Result};
+use log::{debug, info, warn};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Display, Formatter};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The output of a FetchScheduler job, jobs are identified by their index in the params given
+/// to FetchScheduler::run
+#[derive(Debug)]
+pub enum FetchEvent {
+    /// A record fetched by the job
+    Record(usize, FetchRecord),
+    /// The job completed, with the records skipped in Recovery::Skip mode
+    Done(usize, Vec<SkippedRecord>),
+    /// The job failed, no further events follow for it
+    Failed(usize, Error),
+}
+
+/// The progress of a FetchScheduler run, passed to the progress callback whenever a job completes
+#[derive(Clone, Debug)]
+pub struct FetchProgress {
+    /// The number of jobs completed so far
+    pub completed: usize,
+    /// The number of jobs, once the last one was added
+    pub total: Option<usize>,
+    /// The index of the job that completed
+    pub job: usize,
+    pub user: String,
+    pub records: usize,
+    pub elapsed: Duration,
+}
+
+impl Display for FetchProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}/", self.completed)?;
+        match self.total {
+            Some(total) => write!(f, "{}]", total)?,
+            None => f.write_str("?]")?,
+        }
+        write!(
+            f,
+            " job {} of user {} fetched {} records, {:.1}s elapsed",
+            self.job + 1,
+            self.user,
+            self.records,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+// the delay before the first retry of a failed job
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+type ProgressFn = Arc<dyn Fn(&FetchProgress) + Send + Sync>;
+
+/// Runs several doveadm fetches concurrently. At most per_user fetches run for the same user at
+/// any time, so a single user's index is not hammered.
+///
+/// The events of all jobs are delivered in job order whatever order the fetches complete in:
+/// first all events of the first job, then those of the second and so on. Only the jobs among
+/// the next jobs the consumer has not read yet are added, so at most jobs fetches run at any
+/// time and the records of at most jobs jobs are buffered in memory. Shards bound the size of a
+/// job, see FetchParams::shards.
+///
+/// Jobs that fail transiently, see DoveadmErrorKind::is_transient, can be retried after a delay
+/// that doubles with every attempt. A job must not deliver records twice, so with retries the
+/// records of a shard, see FetchParams::is_shard, are held back until the shard succeeded. Shards
+/// are small enough to be held in memory, other jobs pass their records on as they are fetched
+/// and are only retried if they failed before their first record.
+pub struct FetchScheduler {
+    cmd: OsString,
+    jobs: usize,
+    per_user: usize,
+    retries: usize,
+    retry_delay: Duration,
+    recovery: Recovery,
+    progress: Option<ProgressFn>,
+}
+
+impl Default for FetchScheduler {
+    fn default() -> Self {
+        FetchScheduler::new()
+    }
+}
+
+impl FetchScheduler {
+    /// A scheduler running one fetch at a time
+    pub fn new() -> FetchScheduler {
+        FetchScheduler::with_cmd(DOVEADM_CMD)
+    }
+
+    /// Run cmd instead of doveadm, cmd is expected to behave like 'doveadm fetch'
+    pub fn with_cmd<S: AsRef<OsStr>>(cmd: S) -> FetchScheduler {
+        FetchScheduler {
+            cmd: cmd.as_ref().to_owned(),
+            jobs: 1,
+            per_user: 1,
+            retries: 0,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            recovery: Recovery::default(),
+            progress: None,
+        }
+    }
+
+    /// The maximum number of jobs ahead of the consumer and so of concurrent fetches, at least one
+    pub fn set_jobs(&mut self, jobs: usize) -> &mut Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// The maximum number of concurrent fetches of a single user, at least one
+    pub fn set_per_user(&mut self, per_user: usize) -> &mut Self {
+        self.per_user = per_user.max(1);
+        self
+    }
+
+    /// The number of times a job that failed transiently is run again
+    pub fn set_retries(&mut self, retries: usize) -> &mut Self {
+        self.retries = retries;
+        self
+    }
+
+    /// The delay before the first retry of a job, every further retry waits twice as long
+    pub fn set_retry_delay(&mut self, retry_delay: Duration) -> &mut Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    pub fn set_recovery(&mut self, recovery: Recovery) -> &mut Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Call progress whenever a job completes, on the scheduler's dispatcher thread
+    pub fn set_progress<F>(&mut self, progress: F) -> &mut Self
+    where
+        F: Fn(&FetchProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Start fetching with every one of params and return their events. The params are taken
+    /// from the iterator only as the consumer reaches them, so building them can be left to the
+    /// iterator until they are about to be fetched.
+    pub fn run<'a, I>(&self, params: I) -> FetchEvents<'a>
+    where
+        I: IntoIterator<Item = FetchParams>,
+        I::IntoIter: 'a,
+    {
+        let dispatcher = Dispatcher {
+            cmd: self.cmd.clone(),
+            per_user: self.per_user,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            recovery: self.recovery,
+            progress: self.progress.clone(),
+        };
+        let (notices, notice_receiver) = mpsc::channel();
+        let job_notices = notices.clone();
+        let mut events = FetchEvents {
+            params: Some(Box::new(params.into_iter())),
+            jobs: self.jobs,
+            added: 0,
+            receivers: VecDeque::with_capacity(self.jobs),
+            notices,
+            dispatcher: Some(thread::spawn(move || {
+                dispatcher.dispatch(notice_receiver, job_notices)
+            })),
+        };
+        events.add_jobs();
+        events
+    }
+}
+
+/// The events of the jobs of a FetchScheduler in job order, see FetchScheduler
+pub struct FetchEvents<'a> {
+    // the params of the jobs not added yet, None once all are added
+    params: Option<Box<dyn Iterator<Item = FetchParams> + 'a>>,
+    jobs: usize,
+    added: usize,
+    // the jobs added and not consumed yet
+    receivers: VecDeque<(usize, Receiver<FetchEvent>)>,
+    notices: Sender<Notice>,
+    dispatcher: Option<JoinHandle<()>>,
+}
+
+impl FetchEvents<'_> {
+    // pass the jobs within reach of the consumer on to the dispatcher
+    fn add_jobs(&mut self) {
+        while self.receivers.len() < self.jobs {
+            let params = match self.params.as_mut() {
+                Some(params) => params.next(),
+                None => return,
+            };
+            match params {
+                Some(params) => {
+                    let idx = self.added;
+                    let (sender, receiver) = mpsc::channel();
+                    self.receivers.push_back((idx, receiver));
+                    self.added += 1;
+                    let _ = self.notices.send(Notice::Job(Job {
+                        idx,
+                        params,
+                        sender,
+                    }));
+                }
+                None => {
+                    self.params = None;
+                    let _ = self.notices.send(Notice::LastJob);
+                }
+            }
+        }
+    }
+
+    // the consumer is done with the front job, the next one may be added
+    fn pop_front(&mut self) {
+        self.receivers.pop_front();
+        self.add_jobs();
+    }
+}
+
+impl Iterator for FetchEvents<'_> {
+    type Item = FetchEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // add_jobs keeps jobs queued until all have been added
+        let (idx, receiver) = match self.receivers.front() {
+            Some(front) => front,
+            None => {
+                if let Some(dispatcher) = self.dispatcher.take() {
+                    if dispatcher.join().is_err() {
+                        warn!("FetchEvents::next: fetch dispatcher panicked");
+                    }
+                }
+                return None;
+            }
+        };
+        let idx = *idx;
+        match receiver.recv() {
+            Ok(FetchEvent::Record(idx, record)) => Some(FetchEvent::Record(idx, record)),
+            Ok(event) => {
+                self.pop_front();
+                Some(event)
+            }
+            // the job ended without an event, ie. its thread panicked
+            Err(_) => {
+                self.pop_front();
+                Some(FetchEvent::Failed(
+                    idx,
+                    anyhow!("fetch job {} terminated unexpectedly", idx),
+                ))
+            }
+        }
+    }
+}
+
+impl Drop for FetchEvents<'_> {
+    // no further jobs are started once the consumer has gone away, running jobs end with their
+    // next record
+    fn drop(&mut self) {
+        if self.dispatcher.is_some() {
+            let _ = self.notices.send(Notice::Stop);
+        }
+    }
+}
+
+// what the dispatcher waits for
+enum Notice {
+    // a job's fetch finished, with the user and the number of records delivered
+    Finished(usize, String, usize),
+    // a job within reach of the consumer
+    Job(Job),
+    // no further jobs follow
+    LastJob,
+    // the consumer has gone away
+    Stop,
+}
+
+struct Job {
+    idx: usize,
+    params: FetchParams,
+    sender: Sender<FetchEvent>,
+}
+
+// starts the jobs as the limits allow
+struct Dispatcher {
+    cmd: OsString,
+    per_user: usize,
+    retries: usize,
+    retry_delay: Duration,
+    recovery: Recovery,
+    progress: Option<ProgressFn>,
+}
+
+impl Dispatcher {
+    fn dispatch(&self, notices: Receiver<Notice>, job_notices: Sender<Notice>) {
+        // the consumer limits the jobs, see FetchEvents::add_jobs, the per user limit counts
+        // running fetches. A job of a user at the limit waits, but the fetch blocking it ends
+        // without the consumer, so the job the consumer waits for can always be started.
+        let mut pending: Vec<Job> = Vec::new();
+        let mut running_per_user: HashMap<String, usize> = HashMap::new();
+        let mut added = 0;
+        let mut last_added = false;
+        let mut completed = 0;
+        let started = Instant::now();
+
+        while !(last_added && completed == added) {
+            // start the pending jobs in order, skipping those of users at their limit
+            let mut idx = 0;
+            while idx < pending.len() {
+                let user_running = running_per_user
+                    .get(pending[idx].params.user())
+                    .copied()
+                    .unwrap_or(0);
+                if user_running < self.per_user {
+                    let job = pending.remove(idx);
+                    *running_per_user
+                        .entry(job.params.user().to_owned())
+                        .or_default() += 1;
+                    self.start(job, job_notices.clone());
+                } else {
+                    idx += 1;
+                }
+            }
+
+            // the dispatcher holds a sender, so this can not fail
+            match notices.recv() {
+                Ok(Notice::Job(job)) => {
+                    added += 1;
+                    pending.push(job);
+                }
+                Ok(Notice::LastJob) => last_added = true,
+                Ok(Notice::Finished(idx, user, records)) => {
+                    if let Some(user_running) = running_per_user.get_mut(&user) {
+                        *user_running -= 1;
+                    }
+                    completed += 1;
+                    if let Some(progress) = &self.progress {
+                        progress(&FetchProgress {
+                            completed,
+                            total: last_added.then_some(added),
+                            job: idx,
+                            user,
+                            records,
+                            elapsed: started.elapsed(),
+                        });
+                    }
+                }
+                Ok(Notice::Stop) | Err(_) => {
+                    debug!("Dispatcher::dispatch: the consumer has gone away");
+                    return;
+                }
+            }
+        }
+        info!(
+            "Dispatcher::dispatch: {} fetch jobs completed in {:.1}s",
+            added,
+            started.elapsed().as_secs_f64()
+        );
+    }
+
+    fn start(&self, job: Job, done: Sender<Notice>) {
+        let cmd = self.cmd.clone();
+        let retries = self.retries;
+        let retry_delay = self.retry_delay;
+        let recovery = self.recovery;
+        debug!(
+            "Dispatcher::start: starting job {} with parameters {:?}",
+            job.idx, job.params
+        );
+        thread::spawn(move || {
+            let Job {
+                idx,
+                params,
+                sender,
+            } = job;
+            let user = params.user().to_owned();
+            let mut records = 0;
+            let mut attempt = 0;
+            let hold = retries > 0 && params.is_shard();
+            let event = loop {
+                let mut held = Vec::new();
+                let res = fetch(&cmd, params.clone(), recovery, |record| {
+                    if hold {
+                        held.push(record);
+                        true
+                    } else {
+                        records += 1;
+                        sender.send(FetchEvent::Record(idx, record)).is_ok()
+                    }
+                });
+                match res {
+                    Ok(skipped) => {
+                        records += held.len();
+                        for record in held {
+                            if sender.send(FetchEvent::Record(idx, record)).is_err() {
+                                break;
+                            }
+                        }
+                        break FetchEvent::Done(idx, skipped);
+                    }
+                    Err(err)
+                        if attempt < retries && (hold || records == 0) && is_transient(&err) =>
+                    {
+                        let delay = retry_delay.saturating_mul(2u32.saturating_pow(attempt as u32));
+                        attempt += 1;
+                        warn!(
+                            "Dispatcher::start: job {} failed, retry {} of {} in {:.1}s: {:#}",
+                            idx,
+                            attempt,
+                            retries,
+                            delay.as_secs_f64(),
+                            err
+                        );
+                        thread::sleep(delay);
+                    }
+                    Err(err) => break FetchEvent::Failed(idx, err),
+                }
+            };
+            // the consumer may have gone away, the dispatcher has not
+            let _ = sender.send(event);
+            let _ = done.send(Notice::Finished(idx, user, records));
+        });
+    }
+}
+
+// doveadm failures that may not recur, parser errors are not retried as the output would be the
+// same
+fn is_transient(err: &Error) -> bool {
+    err.downcast_ref::<DoveadmError>()
+        .is_some_and(|err| err.kind.is_transient())
+}
+
+// fetch with params and pass the records to deliver until it returns false, returns the records
+// skipped
+fn fetch(
+    cmd: &OsStr,
+    params: FetchParams,
+    recovery: Recovery,
+    mut deliver: impl FnMut(FetchRecord) -> bool,
+) -> Result<Vec<SkippedRecord>> {
+    let mut fetch = DoveadmFetch::with_cmd(cmd, params)?;
+    fetch.set_recovery(recovery);
+    for record in &mut fetch {
+        if !deliver(record?) {
+            break;
+        }
+    }
+    Ok(fetch.skipped().to_vec())
+}
@@ -0,0 +1,70 @@
+use crate::doveadm::{
+    FetchEvent, FetchEvents, FetchParams, FetchRecord, FetchScheduler, SkippedRecord,
+};
+use anyhow::Result;
+
+/// Fetches a single mailbox in UID ranges, see FetchParams::shards, and presents the union of
+/// the shards as one record stream in UID range order. The shards are fetched by a
+/// FetchScheduler, concurrently as far as its limits allow - all shards are fetched for the same
+/// user, so per_user applies. Failed shards are retried as configured in the scheduler.
+///
+/// Like DoveadmFetch the iteration ends after the last record or after the first error, the
+/// failure of a shard ends the stream.
+pub struct ShardedFetch {
+    events: FetchEvents<'static>,
+    shards: usize,
+    skipped: Vec<SkippedRecord>,
+    failed: bool,
+}
+
+impl ShardedFetch {
+    /// Fetch with params in shards of shard_size UIDs, the last one open-ended, see
+    /// FetchParams::shards
+    pub fn new(
+        scheduler: &FetchScheduler,
+        params: &FetchParams,
+        uid_next: u64,
+        shard_size: u64,
+    ) -> ShardedFetch {
+        let shards = params.shards(uid_next, shard_size);
+        ShardedFetch {
+            shards: shards.len(),
+            events: scheduler.run(shards),
+            skipped: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// The number of shards fetched
+    pub fn shards(&self) -> usize {
+        self.shards
+    }
+
+    /// The records skipped so far in Recovery::Skip mode, line numbers refer to the output of the
+    /// shard
+    pub fn skipped(&self) -> &[SkippedRecord] {
+        &self.skipped
+    }
+}
+
+impl Iterator for ShardedFetch {
+    type Item = Result<FetchRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            match self.events.next()? {
+                FetchEvent::Record(_, record) => return Some(Ok(record)),
+                FetchEvent::Done(_, skipped) => self.skipped.extend(skipped),
+                FetchEvent::Failed(shard, err) => {
+                    self.failed = true;
+                    return Some(Err(
+                        err.context(format!("failed to fetch shard {}", shard + 1))
+                    ));
+                }
+            }
+        }
+    }
+}
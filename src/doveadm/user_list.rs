@@ -0,0 +1,47 @@
+use crate::doveadm::DoveadmCmd;
+use anyhow::Result;
+use std::ffi::OsStr;
+
+/// Enumerates the users of the userdb with doveadm user
+pub struct DoveadmUserList {
+    cmd: DoveadmCmd,
+}
+
+impl Default for DoveadmUserList {
+    fn default() -> Self {
+        DoveadmUserList::new()
+    }
+}
+
+impl DoveadmUserList {
+    pub fn new() -> DoveadmUserList {
+        DoveadmUserList {
+            cmd: DoveadmCmd::new(),
+        }
+    }
+
+    /// Run cmd instead of doveadm, cmd is expected to behave like 'doveadm user'
+    pub fn with_cmd<S: AsRef<OsStr>>(cmd: S) -> DoveadmUserList {
+        DoveadmUserList {
+            cmd: DoveadmCmd::with_cmd(cmd),
+        }
+    }
+
+    /// The users matching mask, which may contain the wildcards '*' and '?', in the order the
+    /// userdb returns them. A mask without wildcards matches only that user.
+    pub fn list(&self, mask: &str) -> Result<Vec<String>> {
+        let output = self.cmd.run(&["user".to_owned(), mask.to_owned()])?;
+        if mask.contains(['*', '?']) {
+            Ok(String::from_utf8_lossy(&output)
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_owned())
+                .collect())
+        } else {
+            // without wildcards doveadm prints the userdb fields of the user, failing if the user
+            // does not exist
+            Ok(vec![mask.to_owned()])
+        }
+    }
+}
@@ -1,46 +1,470 @@
-use anyhow::{anyhow, Result};
-use log::info;
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
 use mod_logger::Logger;
 use nix::unistd::getuid;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
 
-mod doveadm;
-use crate::doveadm::{DoveadmFetch, FetchParams, SearchParam};
+pub mod analysis;
+use crate::analysis::{
+    write_totals, AgeReport, AttachmentReport, DuplicateReport, FolderReport, ListReport, Report,
+    ReportFormat, SenderReport, SizeReport,
+};
+
+pub mod dedup;
+use crate::dedup::{plan_removals, DuplicateRemover, RemovalMethod};
+
+pub mod doveadm;
+pub mod mail;
+use crate::doveadm::{
+    AnalyseCmd, DoveadmCmd, DoveadmMailboxList, DoveadmUserList, FetchEvent, FetchEvents,
+    FetchParams, FetchRecord, FetchScheduler, ImapField, SearchParam,
+};
 pub use doveadm::CmdArgs;
 
+// fetched if neither a query nor all mailboxes are given, all messages of it are fetched, not just
+// the seen ones as before queries were supported
+const DEFAULT_MAILBOX: &str = "INBOX";
+// the user mask of all_users
+const ALL_USERS: &str = "*";
+
 pub fn fetch(cmd_args: CmdArgs) -> Result<()> {
     Logger::set_default_level(cmd_args.log_level);
     Logger::set_color(true);
     Logger::set_brief_info(true);
 
-    if ! getuid().is_root() {
+    if !getuid().is_root() {
         return Err(anyhow!("please run this command as root"));
     }
 
+    analyse(&cmd_args, &mut std::io::stdout().lock())
+}
 
-    let mut fetch_params = FetchParams::new(cmd_args.user);
+/// Run the analysis selected by cmd_args and write the results to out
+pub fn analyse(cmd_args: &CmdArgs, out: &mut dyn Write) -> Result<()> {
+    match &cmd_args.command {
+        AnalyseCmd::Fetch { fields } => dump_records(cmd_args, fields, out),
+        AnalyseCmd::Folders => for_users(
+            cmd_args,
+            &selected_users(cmd_args)?,
+            out,
+            FolderReport::default(),
+            |user, out, rollup| {
+                let report = FolderReport::new(
+                    DoveadmMailboxList::with_cmd(&cmd_args.doveadm, user.to_owned()).mailboxes()?,
+                );
+                cmd_args.output.write(out, &report)?;
+                if let Some(rollup) = rollup {
+                    rollup.merge(&report);
+                }
+                Ok(())
+            },
+        ),
+        AnalyseCmd::Senders => fetch_reports(cmd_args, out, SenderReport::new, |_, _, _| Ok(())),
+        AnalyseCmd::Sizes { top } => {
+            fetch_reports(cmd_args, out, || SizeReport::new(*top), |_, _, _| Ok(()))
+        }
+        AnalyseCmd::Lists => fetch_reports(cmd_args, out, ListReport::new, |_, _, _| Ok(())),
+        AnalyseCmd::Attachments { min_size } => fetch_reports(
+            cmd_args,
+            out,
+            || AttachmentReport::new(*min_size),
+            |_, _, _| Ok(()),
+        ),
+        AnalyseCmd::Age => {
+            let now = chrono::Local::now().into();
+            fetch_reports(cmd_args, out, || AgeReport::new(now), |_, _, _| Ok(()))
+        }
+        AnalyseCmd::Duplicates(args) => {
+            let method = if let Some(mailbox) = &args.quarantine {
+                Some(RemovalMethod::Quarantine(mailbox.clone()))
+            } else if args.expunge {
+                Some(RemovalMethod::Expunge)
+            } else {
+                None
+            };
+            // a single action log for all users
+            let mut log = match &method {
+                Some(_) => {
+                    let log_path = args.action_log.clone().unwrap_or_else(|| {
+                        PathBuf::from(format!(
+                            "duplicates-{}{}.log",
+                            cmd_args
+                                .user
+                                .as_ref()
+                                .map_or_else(String::new, |user| format!("{}-", user)),
+                            chrono::Local::now().format("%Y%m%d-%H%M%S")
+                        ))
+                    });
+                    let log = File::create(&log_path).with_context(|| {
+                        format!("failed to create action log {}", log_path.display())
+                    })?;
+                    Some((log_path, BufWriter::new(log)))
+                }
+                None => None,
+            };
 
-    fetch_params
-        .add_search_param(SearchParam::Mailbox("INBOX".to_owned()))
-        .add_search_param(SearchParam::Seen);
+            fetch_reports(
+                cmd_args,
+                out,
+                || DuplicateReport::new(args.content_hash),
+                |user, out, report| {
+                    if let (Some(method), Some((_, log))) = (&method, &mut log) {
+                        let removals = plan_removals(&report.duplicates(), &args.keep);
+                        let summary = DuplicateRemover::new(user.to_owned(), method.clone(), log)
+                            .set_cmd(DoveadmCmd::with_cmd(&cmd_args.doveadm))
+                            .set_dry_run(!args.execute)
+                            .remove(&removals)?;
+                        if args.execute {
+                            writeln!(out, "\n{}", summary)?;
+                        } else {
+                            writeln!(out, "\ndry run: {}", summary)?;
+                        }
+                    }
+                    Ok(())
+                },
+            )?;
+            if let Some((log_path, _)) = log {
+                writeln!(out, "actions logged to {}", log_path.display())?;
+            }
+            Ok(())
+        }
+    }
+}
 
-    cmd_args.fields.iter().for_each(|field| {
-        let _ = fetch_params.add_field(field.clone());
-    });
+// the users selected by cmd_args, the user given, all users or the users matching user_mask
+fn selected_users(cmd_args: &CmdArgs) -> Result<Vec<String>> {
+    let user_list = DoveadmUserList::with_cmd(&cmd_args.doveadm);
+    match (&cmd_args.user, &cmd_args.user_mask) {
+        (Some(user), _) => Ok(vec![user.clone()]),
+        (None, Some(mask)) => user_list.list(mask),
+        (None, None) if cmd_args.all_users => user_list.list(ALL_USERS),
+        (None, None) => Err(anyhow!(
+            "one of --user, --all-users or --user-mask is required"
+        )),
+    }
+}
+
+// run analyse for the user or, with all_users or user_mask, for every one of users in order. With
+// several users the results of each user are headed by the user name, users that fail get an
+// error entry and the results of the other users are rolled up in rollup.
+fn for_users<R: Display>(
+    cmd_args: &CmdArgs,
+    users: &[String],
+    out: &mut dyn Write,
+    mut rollup: R,
+    mut analyse: impl FnMut(&str, &mut dyn Write, Option<&mut R>) -> Result<()>,
+) -> Result<()> {
+    if let Some(user) = &cmd_args.user {
+        return analyse(user, out, None);
+    }
+
+    let mut failed = Vec::new();
+    for user in users {
+        writeln!(out, "user {}:\n", user)?;
+        if let Err(err) = analyse(user, out, Some(&mut rollup)) {
+            warn!("for_users: analysis of user {} failed: {:#}", user, err);
+            writeln!(out, "error: {:#}", err)?;
+            failed.push((user, err));
+        }
+        writeln!(out)?;
+    }
 
-    info!("fetch: calling doveadm with parameters {:?}", fetch_params);
-    let mut doveadm = DoveadmFetch::new(fetch_params)?;
-    while let Some(record) = doveadm.parse_record()? {
-        info!("fetch: Got: \n {:?}", record);
+    writeln!(out, "all {} users:\n", users.len())?;
+    cmd_args.output.write(out, &rollup)?;
+    if !failed.is_empty() {
+        writeln!(out, "\n{} users failed:", failed.len())?;
+        for (user, err) in failed {
+            writeln!(out, "{}: {:#}", user, err)?;
+        }
     }
-    todo!()
+    Ok(())
 }
 
+// run the reports created by new_report for the users selected by cmd_args, see for_users and
+// UserFetches. finish is called with every report once it is printed. The roll up holds the
+// aggregates of the reports, see Report::roll_up, and the records skipped.
+fn fetch_reports<R: Report>(
+    cmd_args: &CmdArgs,
+    out: &mut dyn Write,
+    new_report: impl Fn() -> R,
+    mut finish: impl FnMut(&str, &mut dyn Write, &R) -> Result<()>,
+) -> Result<()> {
+    let report = new_report();
+    let fields = report.required_fields();
+    let mut fetches = UserFetches::start(cmd_args, &fields)?;
+    let users = std::mem::take(&mut fetches.users);
+    for_users(
+        cmd_args,
+        &users,
+        out,
+        SkippedRollup::new(report.new_rollup()),
+        |user, out, rollup| {
+            let mut report = new_report();
+            let skipped = fetches.next_user(|record| {
+                debug!("fetch: Got: \n {:?}", record);
+                report.add_record(&record);
+            })?;
+            cmd_args.output.write(out, &report)?;
+            cmd_args.output.write(out, &Skipped(skipped))?;
+            if let Some(rollup) = rollup {
+                report.roll_up(&mut rollup.rollup);
+                rollup.skipped += skipped;
+            }
+            finish(user, out, &report)
+        },
+    )
+}
+
+// print the records with fields fetched for the users selected by cmd_args
+fn dump_records(cmd_args: &CmdArgs, fields: &[ImapField], out: &mut dyn Write) -> Result<()> {
+    if cmd_args.output != ReportFormat::Text {
+        return Err(anyhow!(
+            "the fetched records are printed as text, --output applies to the reports only"
+        ));
+    }
+    let mut fetches = UserFetches::start(cmd_args, fields)?;
+    let users = std::mem::take(&mut fetches.users);
+    for_users(
+        cmd_args,
+        &users,
+        out,
+        SkippedRollup::new(RecordCount::default()),
+        |_, out, rollup| {
+            let mut records = 0;
+            let mut res = Ok(());
+            let skipped = fetches.next_user(|record| {
+                if res.is_ok() {
+                    res = writeln!(out, "{}", record);
+                }
+                records += 1;
+            })?;
+            res?;
+            writeln!(out, "{} records", records)?;
+            if skipped > 0 {
+                writeln!(out, "skipped {} malformed records", skipped)?;
+            }
+            if let Some(rollup) = rollup {
+                rollup.rollup.0 += records;
+                rollup.skipped += skipped;
+            }
+            Ok(())
+        },
+    )
+}
+
+// the roll up of dump_records
+#[derive(Default)]
+struct RecordCount(usize);
+
+impl Display for RecordCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} records", self.0)
+    }
+}
+
+// a roll up of fetched records and the number of malformed records skipped for all users
+struct SkippedRollup<R> {
+    rollup: R,
+    skipped: usize,
+}
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
+impl<R> SkippedRollup<R> {
+    fn new(rollup: R) -> SkippedRollup<R> {
+        SkippedRollup { rollup, skipped: 0 }
+    }
+}
+
+impl<R: Display> Display for SkippedRollup<R> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.rollup, f)?;
+        Display::fmt(&Skipped(self.skipped), f)
+    }
+}
+
+// the number of malformed records skipped, printed only if there are any
+struct Skipped(usize);
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.0 == 0 {
+            return Ok(());
+        }
+        write_totals(
+            f,
+            format_args!("skipped {} malformed records", self.0),
+            &[("skipped", self.0.to_string())],
+        )
+    }
+}
+
+// the fetches of the users selected by cmd_args. The fetches of all users are run concurrently as
+// far as jobs and jobs_per_user allow, the records are consumed user by user in the order of users
+// nonetheless. The fetch jobs of a user are built when the scheduler reaches the user, so the
+// mailboxes are listed just before they are fetched.
+struct UserFetches<'a> {
+    users: Vec<String>,
+    // the number of fetch jobs of every user whose jobs were built, or why there are none
+    jobs: Rc<RefCell<VecDeque<Result<usize>>>>,
+    events: FetchEvents<'a>,
+}
+
+impl<'a> UserFetches<'a> {
+    fn start(cmd_args: &'a CmdArgs, fields: &'a [ImapField]) -> Result<UserFetches<'a>> {
+        let users = selected_users(cmd_args)?;
+        let jobs = Rc::new(RefCell::new(VecDeque::with_capacity(users.len())));
+        let user_jobs = Rc::clone(&jobs);
+        let params = users.clone().into_iter().flat_map(move |user| {
+            match fetch_params(cmd_args, &user, fields) {
+                Ok(params) => {
+                    user_jobs.borrow_mut().push_back(Ok(params.len()));
+                    params
+                }
+                Err(err) => {
+                    user_jobs.borrow_mut().push_back(Err(err));
+                    Vec::new()
+                }
+            }
+        });
+
+        info!("fetch: fetching the records of {} users", users.len());
+        let mut scheduler = FetchScheduler::with_cmd(&cmd_args.doveadm);
+        scheduler
+            .set_jobs(cmd_args.jobs)
+            .set_per_user(cmd_args.jobs_per_user)
+            .set_retries(cmd_args.retries)
+            .set_recovery(cmd_args.recovery);
+        if cmd_args.progress {
+            scheduler.set_progress(|progress| info!("{}", progress));
+        }
+        let events = scheduler.run(params);
+        Ok(UserFetches {
+            users,
+            jobs,
+            events,
+        })
+    }
+
+    // pass the records of the next user to add and return the number of records skipped. The
+    // events of all fetches of the user are consumed even if one fails, records fetched after the
+    // failure are left out.
+    fn next_user(&mut self, mut add: impl FnMut(FetchRecord)) -> Result<usize> {
+        // the jobs of the next user have been built, unless there are no more users: the jobs
+        // of the users before were consumed and the scheduler always reaches the next job
+        let jobs = self
+            .jobs
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| anyhow!("no fetch jobs left"))??;
+        let mut skipped = 0;
+        let mut failure = None;
+        let mut completed = 0;
+        while completed < jobs {
+            match self.events.next() {
+                Some(FetchEvent::Record(_, record)) => {
+                    if failure.is_none() {
+                        add(record);
+                    }
+                }
+                Some(FetchEvent::Done(_, job_skipped)) => {
+                    completed += 1;
+                    skipped += job_skipped.len();
+                }
+                Some(FetchEvent::Failed(_, err)) => {
+                    completed += 1;
+                    failure.get_or_insert(err);
+                }
+                None => return Err(anyhow!("fetch jobs ended unexpectedly")),
+            }
+        }
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(skipped),
+        }
+    }
+}
+
+// the params of the doveadm fetch calls of fields for user. With all_mailboxes every mailbox is
+// fetched separately, with a shard size every mailbox is fetched in UID ranges.
+fn fetch_params(cmd_args: &CmdArgs, user: &str, fields: &[ImapField]) -> Result<Vec<FetchParams>> {
+    // a query of several search keys is parsed as a group, pass them on individually
+    let query = match &cmd_args.query {
+        Some(SearchParam::Group(params)) => params.clone(),
+        Some(param) => vec![param.clone()],
+        None if cmd_args.all_mailboxes => Vec::new(),
+        None => vec![SearchParam::Mailbox(DEFAULT_MAILBOX.to_owned())],
+    };
+
+    let new_params = |mailbox: Option<&str>| {
+        let mut fetch_params = FetchParams::new(user.to_owned());
+        query.iter().for_each(|param| {
+            fetch_params.add_search_param(param.clone());
+        });
+        if let Some(mailbox) = mailbox {
+            fetch_params.add_search_param(SearchParam::Mailbox(mailbox.to_owned()));
+        }
+        for field in fields {
+            if !fetch_params.fields().contains(field) {
+                fetch_params.add_field(field.clone());
+            }
+        }
+        if let Some(format) = cmd_args.format {
+            fetch_params.set_format(format);
+        }
+        fetch_params
+    };
+
+    // the fetches and the mailbox each of them is restricted to, if known
+    let mailbox_list = DoveadmMailboxList::with_cmd(&cmd_args.doveadm, user.to_owned());
+    let params = if cmd_args.all_mailboxes {
+        mailbox_list
+            .list()?
+            .into_iter()
+            .map(|mailbox| {
+                let params = new_params(Some(&mailbox));
+                (Some(mailbox), params)
+            })
+            .collect()
+    } else {
+        vec![(single_mailbox(&query), new_params(None))]
+    };
+    if cmd_args.shard_size == 0 {
+        return Ok(params.into_iter().map(|(_, params)| params).collect());
+    }
+
+    let mut res = Vec::new();
+    for (mailbox, params) in params {
+        match mailbox {
+            Some(mailbox) => {
+                let uid_next = mailbox_list.status(&mailbox)?.uid_next;
+                res.extend(params.shards(uid_next, cmd_args.shard_size));
+            }
+            None => {
+                warn!(
+                    "fetch: not sharding the fetch of user {}, the query does not select a single \
+                     mailbox",
+                    user
+                );
+                res.push(params);
+            }
+        }
+    }
+    Ok(res)
+}
+
+// the mailbox query is restricted to if it names exactly one mailbox without wildcards
+fn single_mailbox(query: &[SearchParam]) -> Option<String> {
+    let mut mailboxes = query.iter().filter_map(|param| match param {
+        SearchParam::Mailbox(mailbox) => Some(mailbox),
+        _ => None,
+    });
+    match (mailboxes.next(), mailboxes.next()) {
+        (Some(mailbox), None) if !mailbox.contains(['*', '%']) => Some(mailbox.clone()),
+        _ => None,
     }
 }
@@ -0,0 +1,22 @@
+mod address;
+pub use address::{parse_address_list, Address, Mailbox};
+
+mod charset;
+pub use charset::{decode_8bit, decode_charset};
+
+mod date;
+pub use date::parse_date;
+
+mod encoded_word;
+pub use encoded_word::decode_encoded_words;
+
+mod header;
+pub use header::{unfold, Header};
+
+mod imap;
+pub use imap::{parse_imap_value, BodyStructure, Envelope, ImapValue};
+
+mod mime;
+pub use mime::{
+    decode_base64, decode_quoted_printable, decode_transfer_encoding, MimePart, ParamValue,
+};
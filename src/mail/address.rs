@@ -0,0 +1,488 @@
+use crate::mail::decode_encoded_words;
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+
+const SPECIALS: &str = "()<>[]:;@\\,\"";
+
+/// A single mailbox as in RFC 5322 - a display name and an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mailbox {
+    /// Display name with encoded words decoded, a trailing comment is used if there is none
+    pub display_name: Option<String>,
+    /// The local part, unquoted
+    pub local_part: String,
+    /// The domain, domain literals keep their brackets
+    pub domain: String,
+}
+
+impl Mailbox {
+    /// The address as local-part@domain, the local part is quoted if required
+    pub fn addr_spec(&self) -> String {
+        if self.local_part.split('.').all(is_atom) {
+            format!("{}@{}", self.local_part, self.domain)
+        } else {
+            format!("{}@{}", quote(&self.local_part), self.domain)
+        }
+    }
+
+    /// The lowercased address, the key analyses group senders and recipients by
+    pub fn normalized(&self) -> String {
+        self.addr_spec().to_lowercase()
+    }
+
+    /// The lowercased domain
+    pub fn normalized_domain(&self) -> String {
+        self.domain.to_lowercase()
+    }
+}
+
+impl Display for Mailbox {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.display_name {
+            Some(name) => write!(f, "{} <{}>", name, self.addr_spec()),
+            None => write!(f, "{}", self.addr_spec()),
+        }
+    }
+}
+
+/// An entry of an address list, either a mailbox or a named group of mailboxes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Mailbox(Mailbox),
+    Group(String, Vec<Mailbox>),
+}
+
+impl Address {
+    /// The mailbox or the members of the group
+    pub fn mailboxes(&self) -> &[Mailbox] {
+        match self {
+            Address::Mailbox(mailbox) => std::slice::from_ref(mailbox),
+            Address::Group(_, mailboxes) => mailboxes.as_slice(),
+        }
+    }
+}
+
+/// Parse an unfolded address list header value like From, To, Cc or Reply-To.
+///
+/// Handles display names with encoded words, quoted strings, comments, groups and the obsolete
+/// syntax of RFC 5322 (routes, empty list elements). Empty values result in an empty list.
+pub fn parse_address_list(value: &str) -> Result<Vec<Address>> {
+    let mut parser = AddressParser {
+        tokens: tokenize(value)?,
+        pos: 0,
+    };
+    let mut res = Vec::new();
+    loop {
+        match parser.peek() {
+            None => break,
+            Some(Token::Special(',')) => {
+                parser.pos += 1;
+            }
+            Some(_) => {
+                res.push(parser.parse_address()?);
+                match parser.next() {
+                    None | Some(Token::Special(',')) => (),
+                    Some(token) => {
+                        return Err(anyhow!(
+                            "address list: unexpected {} after address in '{}'",
+                            token,
+                            value
+                        ))
+                    }
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Atom(String),
+    Quoted(String),
+    DomainLiteral(String),
+    Comment(String),
+    Special(char),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Atom(value) => write!(f, "'{}'", value),
+            Token::Quoted(value) => write!(f, "\"{}\"", value),
+            Token::DomainLiteral(value) => write!(f, "'{}'", value),
+            Token::Comment(value) => write!(f, "({})", value),
+            Token::Special(ch) => write!(f, "'{}'", ch),
+        }
+    }
+}
+
+// atoms include '.' so dot-atoms and obsolete phrases come out as one token
+fn tokenize(value: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => (),
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => quoted.extend(chars.next()),
+                        Some(ch) => quoted.push(ch),
+                        None => {
+                            return Err(anyhow!(
+                                "address list: unterminated quoted string in '{}'",
+                                value
+                            ))
+                        }
+                    }
+                }
+                tokens.push(Token::Quoted(quoted));
+            }
+            '(' => {
+                let mut comment = String::new();
+                let mut depth = 1;
+                loop {
+                    match chars.next() {
+                        Some('(') => {
+                            depth += 1;
+                            comment.push('(');
+                        }
+                        Some(')') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                            comment.push(')');
+                        }
+                        Some('\\') => comment.extend(chars.next()),
+                        Some(ch) => comment.push(ch),
+                        None => {
+                            return Err(anyhow!(
+                                "address list: unterminated comment in '{}'",
+                                value
+                            ))
+                        }
+                    }
+                }
+                tokens.push(Token::Comment(comment));
+            }
+            '[' => {
+                let mut literal = String::from('[');
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('\\') => literal.extend(chars.next()),
+                        Some(ch) if !ch.is_whitespace() => literal.push(ch),
+                        Some(_) => (),
+                        None => {
+                            return Err(anyhow!(
+                                "address list: unterminated domain literal in '{}'",
+                                value
+                            ))
+                        }
+                    }
+                }
+                literal.push(']');
+                tokens.push(Token::DomainLiteral(literal));
+            }
+            ch if SPECIALS.contains(ch) => tokens.push(Token::Special(ch)),
+            ch => {
+                let mut atom = ch.to_string();
+                while let Some(ch) =
+                    chars.next_if(|ch| !ch.is_whitespace() && !SPECIALS.contains(*ch))
+                {
+                    atom.push(ch);
+                }
+                tokens.push(Token::Atom(atom));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct AddressParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl AddressParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // skip comments, returns the last one
+    fn skip_comments(&mut self) -> Option<String> {
+        let mut last = None;
+        while let Some(Token::Comment(comment)) = self.peek() {
+            last = Some(comment.clone());
+            self.pos += 1;
+        }
+        last
+    }
+
+    // collect the words of a phrase or local part up to the next special
+    fn words(&mut self) -> Vec<Token> {
+        let mut words = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Atom(_) | Token::Quoted(_) => words.push(token.clone()),
+                Token::Comment(_) => (),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        words
+    }
+
+    fn parse_address(&mut self) -> Result<Address> {
+        let start = self.pos;
+        let words = self.words();
+        match self.peek() {
+            Some(Token::Special(':')) => {
+                self.pos += 1;
+                let name = phrase(&words).unwrap_or_default();
+                let mut mailboxes = Vec::new();
+                loop {
+                    self.skip_comments();
+                    match self.peek() {
+                        // be lenient about a missing ';' at the end
+                        None => break,
+                        Some(Token::Special(';')) => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(Token::Special(',')) => self.pos += 1,
+                        Some(_) => mailboxes.push(self.parse_mailbox()?),
+                    }
+                }
+                self.skip_comments();
+                Ok(Address::Group(name, mailboxes))
+            }
+            _ => {
+                self.pos = start;
+                Ok(Address::Mailbox(self.parse_mailbox()?))
+            }
+        }
+    }
+
+    fn parse_mailbox(&mut self) -> Result<Mailbox> {
+        let words = self.words();
+        match self.next() {
+            Some(Token::Special('<')) => {
+                let (local_part, domain) = self.parse_angle_addr()?;
+                self.skip_comments();
+                Ok(Mailbox {
+                    display_name: phrase(&words),
+                    local_part,
+                    domain,
+                })
+            }
+            Some(Token::Special('@')) if !words.is_empty() => {
+                let local_part = local_part(&words);
+                let domain = self.parse_domain()?;
+                let comment = self.skip_comments();
+                Ok(Mailbox {
+                    display_name: comment
+                        .map(|comment| decode_encoded_words(comment.trim()))
+                        .filter(|name| !name.is_empty()),
+                    local_part,
+                    domain,
+                })
+            }
+            Some(token) => Err(anyhow!(
+                "address list: unexpected {} in mailbox '{}'",
+                token,
+                words_to_string(&words)
+            )),
+            None => Err(anyhow!(
+                "address list: missing '@' in mailbox '{}'",
+                words_to_string(&words)
+            )),
+        }
+    }
+
+    // parse the part after '<' up to and including '>'
+    fn parse_angle_addr(&mut self) -> Result<(String, String)> {
+        self.skip_comments();
+        // obsolete route: <@relay1,@relay2:user@domain>
+        if let Some(Token::Special('@')) = self.peek() {
+            while let Some(token) = self.next() {
+                if token == Token::Special(':') {
+                    break;
+                }
+            }
+        }
+        let words = self.words();
+        if words.is_empty() {
+            return Err(anyhow!("address list: missing local part in angle address"));
+        }
+        match self.next() {
+            Some(Token::Special('@')) => (),
+            _ => {
+                return Err(anyhow!(
+                    "address list: missing '@' in angle address '{}'",
+                    words_to_string(&words)
+                ))
+            }
+        }
+        let domain = self.parse_domain()?;
+        self.skip_comments();
+        match self.next() {
+            Some(Token::Special('>')) => Ok((local_part(&words), domain)),
+            _ => Err(anyhow!(
+                "address list: missing '>' after '{}@{}'",
+                words_to_string(&words),
+                domain
+            )),
+        }
+    }
+
+    fn parse_domain(&mut self) -> Result<String> {
+        self.skip_comments();
+        match self.next() {
+            Some(Token::Atom(domain)) | Some(Token::DomainLiteral(domain)) => Ok(domain),
+            Some(token) => Err(anyhow!("address list: invalid domain {}", token)),
+            None => Err(anyhow!("address list: missing domain")),
+        }
+    }
+}
+
+fn words_to_string(words: &[Token]) -> String {
+    words
+        .iter()
+        .map(|word| word.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// the display name, words are separated by a single space
+fn phrase(words: &[Token]) -> Option<String> {
+    let name = words
+        .iter()
+        .filter_map(|word| match word {
+            Token::Atom(value) | Token::Quoted(value) => Some(value.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let name = decode_encoded_words(name.trim());
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+// the local part, words of the obsolete syntax are concatenated
+fn local_part(words: &[Token]) -> String {
+    words
+        .iter()
+        .filter_map(|word| match word {
+            Token::Atom(value) | Token::Quoted(value) => Some(value.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_atom(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|ch| !ch.is_whitespace() && !ch.is_control() && !SPECIALS.contains(ch))
+}
+
+fn quote(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+    res.push('"');
+    for ch in value.chars() {
+        if ch == '"' || ch == '\\' {
+            res.push('\\');
+        }
+        res.push(ch);
+    }
+    res.push('"');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mailbox(display_name: Option<&str>, local_part: &str, domain: &str) -> Mailbox {
+        Mailbox {
+            display_name: display_name.map(|name| name.to_owned()),
+            local_part: local_part.to_owned(),
+            domain: domain.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_parse_mailboxes() {
+        assert_eq!(
+            parse_address_list(
+                "Alice <Alice@Example.com>, bob@example.org (Bob Builder), \
+                 \"Doe, John\" <john.doe@example.net>"
+            )
+            .unwrap(),
+            vec![
+                Address::Mailbox(mailbox(Some("Alice"), "Alice", "Example.com")),
+                Address::Mailbox(mailbox(Some("Bob Builder"), "bob", "example.org")),
+                Address::Mailbox(mailbox(Some("Doe, John"), "john.doe", "example.net")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        let list = parse_address_list(
+            "undisclosed-recipients:;, Team: a@example.com, B <b@example.com>;, c@example.com",
+        )
+        .unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(
+            list[0],
+            Address::Group("undisclosed-recipients".to_owned(), vec![])
+        );
+        assert_eq!(list[1].mailboxes().len(), 2);
+        assert_eq!(list[1].mailboxes()[1].display_name.as_deref(), Some("B"));
+        assert_eq!(list[2].mailboxes()[0].addr_spec(), "c@example.com");
+    }
+
+    #[test]
+    fn test_parse_special_forms() {
+        let list = parse_address_list(
+            "=?UTF-8?Q?J=C3=B6rg?= M. <joerg@example.de>, \"john doe\"@example.com, \
+             <@relay.example.com:user@[192.0.2.1]>, , (just a comment) x@example.com",
+        )
+        .unwrap();
+        let mailboxes: Vec<&Mailbox> = list.iter().flat_map(Address::mailboxes).collect();
+        assert_eq!(mailboxes.len(), 4);
+        assert_eq!(mailboxes[0].to_string(), "Jörg M. <joerg@example.de>");
+        assert_eq!(mailboxes[1].addr_spec(), "\"john doe\"@example.com");
+        assert_eq!(mailboxes[2].addr_spec(), "user@[192.0.2.1]");
+        assert_eq!(mailboxes[3].normalized(), "x@example.com");
+        assert_eq!(
+            parse_address_list("Alice <ALICE@EXAMPLE.COM>").unwrap()[0].mailboxes()[0]
+                .normalized_domain(),
+            "example.com"
+        );
+        assert!(parse_address_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_address_list("just a name").is_err());
+        assert!(parse_address_list("Alice <alice@example.com").is_err());
+        assert!(parse_address_list("\"unterminated <a@example.com>").is_err());
+        assert!(parse_address_list("a@").is_err());
+    }
+}
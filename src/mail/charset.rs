@@ -0,0 +1,38 @@
+use encoding_rs::Encoding;
+
+/// Decode bytes in the given charset to a string, unknown charsets are decoded as UTF-8.
+/// Invalid sequences are replaced with U+FFFD.
+pub fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match Encoding::for_label(charset.trim().as_bytes()) {
+        Some(encoding) => encoding.decode_without_bom_handling(bytes).0.into_owned(),
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Decode 8-bit data of unknown charset, like raw header lines. Valid UTF-8 is taken as it is,
+/// anything else is decoded as windows-1252, the superset of Latin-1 old mailers mostly used.
+pub fn decode_8bit(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(value) => value.to_owned(),
+        Err(_) => decode_charset(bytes, "windows-1252"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_charset() {
+        assert_eq!(decode_charset(b"Gr\xfc\xdfe", "ISO-8859-1"), "Grüße");
+        assert_eq!(decode_charset(b"\x80", "windows-1252"), "€");
+        assert_eq!(decode_charset("Grüße".as_bytes(), "utf-8"), "Grüße");
+        assert_eq!(decode_charset(b"ab\xff", "x-unknown"), "ab\u{fffd}");
+    }
+
+    #[test]
+    fn test_decode_8bit() {
+        assert_eq!(decode_8bit("Grüße".as_bytes()), "Grüße");
+        assert_eq!(decode_8bit(b"Gr\xfc\xdfe"), "Grüße");
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, FixedOffset};
+
+/// Parse an RFC 2822 date like that of the Date header, ignoring a trailing comment like '(CEST)'
+pub fn parse_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let value = match value.rfind('(') {
+        Some(pos) if value.ends_with(')') => value[..pos].trim_end(),
+        _ => value.as_str(),
+    };
+    DateTime::parse_from_rfc2822(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        let date = parse_date("Tue, 1 Jul 2003 10:52:37 +0200 (CEST)").unwrap();
+        assert_eq!(date.to_rfc3339(), "2003-07-01T10:52:37+02:00");
+        assert!(parse_date("not a date").is_none());
+    }
+}
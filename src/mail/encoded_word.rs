@@ -0,0 +1,147 @@
+use crate::mail::{decode_base64, decode_charset, decode_quoted_printable};
+
+/// Decode RFC 2047 encoded words (=?charset?B|Q?text?=) in a header value.
+///
+/// Whitespace between adjacent encoded words is dropped. Adjacent words in the same charset are
+/// decoded together, so characters split across words by broken mailers survive. Malformed
+/// encoded words are left as they are.
+pub fn decode_encoded_words(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    // undecoded bytes of preceding adjacent encoded words sharing the same charset
+    let mut pending: Option<(String, Vec<u8>)> = None;
+    let mut rest = value;
+
+    while let Some(pos) = rest.find("=?") {
+        let prefix = &rest[..pos];
+        if let Some((charset, bytes, len)) = parse_encoded_word(&rest[pos..]) {
+            let adjacent = prefix.chars().all(char::is_whitespace);
+            match pending.as_mut() {
+                Some((pending_charset, pending_bytes))
+                    if adjacent && pending_charset.eq_ignore_ascii_case(&charset) =>
+                {
+                    pending_bytes.extend_from_slice(&bytes);
+                }
+                _ => {
+                    let had_pending = flush(&mut res, &mut pending);
+                    if !(had_pending && adjacent) {
+                        res.push_str(prefix);
+                    }
+                    pending = Some((charset, bytes));
+                }
+            }
+            rest = &rest[pos + len..];
+        } else {
+            flush(&mut res, &mut pending);
+            res.push_str(&rest[..pos + 2]);
+            rest = &rest[pos + 2..];
+        }
+    }
+    flush(&mut res, &mut pending);
+    res.push_str(rest);
+    res
+}
+
+fn flush(res: &mut String, pending: &mut Option<(String, Vec<u8>)>) -> bool {
+    if let Some((charset, bytes)) = pending.take() {
+        res.push_str(&decode_charset(&bytes, &charset));
+        true
+    } else {
+        false
+    }
+}
+
+// parse an encoded word at the start of value, returns charset, decoded bytes and the length of
+// the encoded word
+fn parse_encoded_word(value: &str) -> Option<(String, Vec<u8>, usize)> {
+    let inner = value.strip_prefix("=?")?;
+    let (charset, rest) = inner.split_once('?')?;
+    let (encoding, rest) = rest.split_once('?')?;
+    let text = &rest[..rest.find("?=")?];
+    if charset.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+    // =?charset?encoding?text?=
+    let len = 2 + charset.len() + 1 + encoding.len() + 1 + text.len() + 2;
+    // RFC 2231 language suffix, eg. =?US-ASCII*EN?Q?...?=
+    let charset = charset.split('*').next()?;
+    let bytes = match encoding {
+        "B" | "b" => decode_base64(text.as_bytes()),
+        "Q" | "q" => decode_q(text),
+        _ => return None,
+    };
+    Some((charset.to_owned(), bytes, len))
+}
+
+// the Q encoding is quoted-printable with underscores for spaces, RFC 2047 section 4.2
+fn decode_q(text: &str) -> Vec<u8> {
+    decode_quoted_printable(text.replace('_', " ").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode_encoded_words("=?UTF-8?B?R3LDvMOfZQ==?= aus Berlin"),
+            "Grüße aus Berlin"
+        );
+        assert_eq!(
+            decode_encoded_words("=?ISO-8859-1?Q?Andr=E9?= Pirard <PIRARD@vm1.ulg.ac.be>"),
+            "André Pirard <PIRARD@vm1.ulg.ac.be>"
+        );
+        assert_eq!(
+            decode_encoded_words("=?iso-8859-1?q?this=20is=20some=20text?="),
+            "this is some text"
+        );
+        assert_eq!(decode_encoded_words("=?utf-8?q?a_b?="), "a b");
+        assert_eq!(decode_encoded_words("=?utf-8?q?a=5Fb=3D?="), "a_b=");
+    }
+
+    #[test]
+    fn test_adjacent_words() {
+        // examples from RFC 2047 section 8
+        assert_eq!(decode_encoded_words("(=?ISO-8859-1?Q?a?=)"), "(a)");
+        assert_eq!(decode_encoded_words("(=?ISO-8859-1?Q?a?= b)"), "(a b)");
+        assert_eq!(
+            decode_encoded_words("(=?ISO-8859-1?Q?a?= =?ISO-8859-1?Q?b?=)"),
+            "(ab)"
+        );
+        assert_eq!(
+            decode_encoded_words("(=?ISO-8859-1?Q?a?=\n  =?ISO-8859-1?Q?b?=)"),
+            "(ab)"
+        );
+        assert_eq!(decode_encoded_words("(=?ISO-8859-1?Q?a_b?=)"), "(a b)");
+        assert_eq!(
+            decode_encoded_words("(=?ISO-8859-1?Q?a?= =?ISO-8859-2?Q?_b?=)"),
+            "(a b)"
+        );
+        // a multi byte character split across two words
+        assert_eq!(
+            decode_encoded_words("=?UTF-8?B?R3LD?= =?UTF-8?B?vMOfZQ==?="),
+            "Grüße"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(decode_encoded_words("=?UTF-8?X?abc?="), "=?UTF-8?X?abc?=");
+        assert_eq!(decode_encoded_words("=?UTF-8?Q?a b?="), "=?UTF-8?Q?a b?=");
+        assert_eq!(decode_encoded_words("plain =? text"), "plain =? text");
+        assert_eq!(
+            decode_encoded_words("=?UTF-8?B?R3LDvMOfZQ"),
+            "=?UTF-8?B?R3LDvMOfZQ"
+        );
+    }
+
+    #[test]
+    fn test_charsets() {
+        assert_eq!(decode_encoded_words("=?windows-1252?Q?=80_5?="), "€ 5");
+        assert_eq!(decode_encoded_words("=?KOI8-R?B?8NLJ18XU?="), "Привет");
+        assert_eq!(
+            decode_encoded_words("=?US-ASCII*EN?Q?Keith_Moore?="),
+            "Keith Moore"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use crate::mail::{decode_encoded_words, parse_address_list, Address};
+use anyhow::Result;
+
+/// A message header, value is the unfolded raw value with encoded words decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub name: String,
+    pub raw: String,
+    pub value: String,
+}
+
+impl Header {
+    pub fn new(name: String, raw: String) -> Header {
+        let value = decode_encoded_words(&unfold(&raw));
+        Header { name, raw, value }
+    }
+
+    /// Parse the header as an address list, for From, To, Cc, Reply-To and the like
+    pub fn addresses(&self) -> Result<Vec<Address>> {
+        parse_address_list(&unfold(&self.raw))
+    }
+}
+
+/// Unfold a header value as described in RFC 5322, line breaks followed by whitespace are
+/// removed
+pub fn unfold(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' if chars.peek().is_some_and(|ch| *ch == ' ' || *ch == '\t') => (),
+            ch => res.push(ch),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unfold() {
+        assert_eq!(unfold("Hello\n World"), "Hello World");
+        assert_eq!(unfold("a\r\n\tb\r\n c"), "a\tb c");
+        assert_eq!(unfold("no fold"), "no fold");
+    }
+
+    #[test]
+    fn test_header() {
+        let header = Header::new(
+            "To".to_owned(),
+            "=?UTF-8?Q?J=C3=B6rg?= <joerg@example.de>,\n \"Doe, John\" <john@example.net>"
+                .to_owned(),
+        );
+        assert_eq!(
+            header.value,
+            "Jörg <joerg@example.de>, \"Doe, John\" <john@example.net>"
+        );
+        assert_eq!(header.addresses().unwrap().len(), 2);
+    }
+}
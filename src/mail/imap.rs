@@ -0,0 +1,8 @@
+mod bodystructure;
+pub use bodystructure::BodyStructure;
+
+mod envelope;
+pub use envelope::Envelope;
+
+mod value;
+pub use value::{parse_imap_value, ImapValue};
@@ -0,0 +1,288 @@
+use crate::mail::imap::{parse_imap_value, Envelope, ImapValue};
+use crate::mail::ParamValue;
+use anyhow::{anyhow, Result};
+
+// nesting deeper than this is rejected
+const MAX_DEPTH: usize = 32;
+
+/// A part of an IMAP BODYSTRUCTURE (or BODY) as described in RFC 3501. Multipart parts contain
+/// their sub parts, message/rfc822 parts the structure of the encapsulated message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BodyStructure {
+    /// type/subtype, lowercased, with the body parameters
+    pub content_type: ParamValue,
+    pub id: Option<String>,
+    pub description: Option<String>,
+    /// The Content-Transfer-Encoding, lowercased, empty for multipart parts
+    pub encoding: String,
+    /// The encoded size in bytes, 0 for multipart parts
+    pub size: u64,
+    /// The size in lines of text and message/rfc822 parts
+    pub lines: Option<u64>,
+    pub md5: Option<String>,
+    pub disposition: Option<ParamValue>,
+    pub language: Vec<String>,
+    pub location: Option<String>,
+    /// The envelope of the encapsulated message of message/rfc822 parts
+    pub envelope: Option<Box<Envelope>>,
+    pub parts: Vec<BodyStructure>,
+}
+
+impl BodyStructure {
+    /// Parse a BODYSTRUCTURE as printed by doveadm fetch imap.bodystructure or imap.body
+    pub fn parse(value: &[u8]) -> Result<BodyStructure> {
+        BodyStructure::from_value(&parse_imap_value(value)?, 0)
+    }
+
+    fn from_value(value: &ImapValue, depth: usize) -> Result<BodyStructure> {
+        if depth > MAX_DEPTH {
+            return Err(anyhow!("bodystructure: nested too deeply"));
+        }
+        let list = value
+            .as_list()
+            .ok_or_else(|| anyhow!("bodystructure: expected a list, found {:?}", value))?;
+        if list.first().and_then(ImapValue::as_list).is_some() {
+            BodyStructure::multipart(list, depth)
+        } else {
+            BodyStructure::single_part(list, depth)
+        }
+    }
+
+    // (part)(part) subtype [params [disposition [language [location]]]]
+    fn multipart(list: &[ImapValue], depth: usize) -> Result<BodyStructure> {
+        let count = list
+            .iter()
+            .take_while(|value| value.as_list().is_some())
+            .count();
+        let parts = list[..count]
+            .iter()
+            .map(|part| BodyStructure::from_value(part, depth + 1))
+            .collect::<Result<Vec<BodyStructure>>>()?;
+        let subtype = list
+            .get(count)
+            .and_then(ImapValue::as_str)
+            .ok_or_else(|| anyhow!("bodystructure: missing multipart subtype"))?;
+        let ext = &list[count + 1..];
+        Ok(BodyStructure {
+            content_type: ParamValue::from_params(
+                &format!("multipart/{}", subtype),
+                params(ext.first())?,
+            ),
+            id: None,
+            description: None,
+            encoding: String::new(),
+            size: 0,
+            lines: None,
+            md5: None,
+            disposition: disposition(ext.get(1))?,
+            language: language(ext.get(2)),
+            location: ext.get(3).and_then(opt_string),
+            envelope: None,
+            parts,
+        })
+    }
+
+    // type subtype params id description encoding size [lines] [md5 [disposition [language
+    // [location]]]], message/rfc822 parts have envelope, body and lines after the size
+    fn single_part(list: &[ImapValue], depth: usize) -> Result<BodyStructure> {
+        if list.len() < 7 {
+            return Err(anyhow!(
+                "bodystructure: expected at least 7 fields, found {}",
+                list.len()
+            ));
+        }
+        let media_type = list[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("bodystructure: missing type"))?
+            .to_lowercase();
+        let subtype = list[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("bodystructure: missing subtype"))?
+            .to_lowercase();
+        let size = list[6]
+            .as_number()
+            .ok_or_else(|| anyhow!("bodystructure: invalid size {:?}", list[6]))?;
+
+        let mut parts = Vec::new();
+        let mut envelope = None;
+        let mut lines = None;
+        let mut idx = 7;
+        if media_type == "message" && subtype == "rfc822" && list.len() >= 10 {
+            envelope = Some(Box::new(Envelope::from_value(&list[7])?));
+            parts.push(BodyStructure::from_value(&list[8], depth + 1)?);
+            lines = list[9].as_number();
+            idx = 10;
+        } else if media_type == "text" {
+            lines = list.get(7).and_then(ImapValue::as_number);
+            idx = 8;
+        }
+        let ext = list.get(idx..).unwrap_or_default();
+
+        Ok(BodyStructure {
+            content_type: ParamValue::from_params(
+                &format!("{}/{}", media_type, subtype),
+                params(Some(&list[2]))?,
+            ),
+            id: opt_string(&list[3]),
+            description: opt_string(&list[4]),
+            encoding: list[5].as_str().unwrap_or("7bit").to_lowercase(),
+            size,
+            lines,
+            md5: ext.first().and_then(opt_string),
+            disposition: disposition(ext.get(1))?,
+            language: language(ext.get(2)),
+            location: ext.get(3).and_then(opt_string),
+            envelope,
+            parts,
+        })
+    }
+
+    pub fn is_multipart(&self) -> bool {
+        self.content_type.value.starts_with("multipart/")
+    }
+
+    /// The file name from the disposition or the name parameter
+    pub fn filename(&self) -> Option<&str> {
+        self.disposition
+            .as_ref()
+            .and_then(|disposition| disposition.param("filename"))
+            .or_else(|| self.content_type.param("name"))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Parts with an attachment disposition or a file name
+    pub fn is_attachment(&self) -> bool {
+        !self.is_multipart()
+            && self.parts.is_empty()
+            && (self
+                .disposition
+                .as_ref()
+                .is_some_and(|disposition| disposition.value == "attachment")
+                || self.filename().is_some())
+    }
+
+    /// The approximate decoded size, base64 encodes 3 bytes in 4 characters
+    pub fn decoded_size(&self) -> u64 {
+        if self.encoding == "base64" {
+            self.size / 4 * 3
+        } else {
+            self.size
+        }
+    }
+
+    /// All parts of the tree that have no sub parts, depth first
+    pub fn leaves(&self) -> Vec<&BodyStructure> {
+        if self.parts.is_empty() {
+            vec![self]
+        } else {
+            self.parts.iter().flat_map(|part| part.leaves()).collect()
+        }
+    }
+}
+
+fn opt_string(value: &ImapValue) -> Option<String> {
+    value.as_str().map(|value| value.to_owned())
+}
+
+// ("name" "value" ...) or NIL
+fn params(value: Option<&ImapValue>) -> Result<Vec<(String, String)>> {
+    match value {
+        None | Some(ImapValue::Nil) => Ok(Vec::new()),
+        Some(ImapValue::List(list)) => list
+            .chunks(2)
+            .map(|pair| match pair {
+                [name, value] => Ok((
+                    name.as_str().unwrap_or_default().to_owned(),
+                    value.as_str().unwrap_or_default().to_owned(),
+                )),
+                _ => Err(anyhow!("bodystructure: odd number of parameter values")),
+            })
+            .collect(),
+        Some(value) => Err(anyhow!(
+            "bodystructure: expected a parameter list, found {:?}",
+            value
+        )),
+    }
+}
+
+// ("attachment" ("filename" "a.pdf")) or NIL
+fn disposition(value: Option<&ImapValue>) -> Result<Option<ParamValue>> {
+    match value {
+        Some(ImapValue::List(list)) => match list.first().and_then(ImapValue::as_str) {
+            Some(name) => Ok(Some(ParamValue::from_params(name, params(list.get(1))?))),
+            None => Err(anyhow!("bodystructure: missing disposition type")),
+        },
+        _ => Ok(None),
+    }
+}
+
+// a string or a list of strings
+fn language(value: Option<&ImapValue>) -> Vec<String> {
+    match value {
+        Some(ImapValue::List(list)) => list.iter().filter_map(opt_string).collect(),
+        Some(value) => opt_string(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_part() {
+        let body = BodyStructure::parse(
+            b"(\"TEXT\" \"PLAIN\" (\"CHARSET\" \"utf-8\") NIL NIL \"7BIT\" 1152 23 NIL NIL \"en\" NIL)",
+        )
+        .unwrap();
+        assert_eq!(body.content_type.value, "text/plain");
+        assert_eq!(body.content_type.param("charset"), Some("utf-8"));
+        assert_eq!(body.encoding, "7bit");
+        assert_eq!(body.size, 1152);
+        assert_eq!(body.lines, Some(23));
+        assert_eq!(body.language, vec!["en"]);
+        assert!(!body.is_attachment());
+    }
+
+    #[test]
+    fn test_multipart() {
+        let body = BodyStructure::parse(
+            b"((\"text\" \"plain\" (\"charset\" \"us-ascii\") NIL NIL \"7bit\" 10 1 NIL NIL NIL NIL)\
+              (\"application\" \"pdf\" (\"name\" \"=?UTF-8?Q?B=C3=BCro.pdf?=\") \"<id@x>\" NIL \"base64\" 4000 \
+               \"md5sum\" (\"attachment\" (\"filename*\" \"utf-8''Gr%C3%BC%C3%9Fe.pdf\")) NIL NIL)\
+              (\"message\" \"rfc822\" NIL NIL NIL \"7bit\" 300 \
+               (NIL \"inner\" NIL NIL NIL NIL NIL NIL NIL NIL) \
+               (\"text\" \"plain\" NIL NIL NIL \"7bit\" 100 5) 12) \
+              \"mixed\" (\"boundary\" \"b1\") NIL NIL NIL)",
+        )
+        .unwrap();
+        assert!(body.is_multipart());
+        assert_eq!(body.content_type.value, "multipart/mixed");
+        assert_eq!(body.content_type.param("boundary"), Some("b1"));
+        assert_eq!(body.parts.len(), 3);
+
+        let pdf = &body.parts[1];
+        assert_eq!(pdf.id.as_deref(), Some("<id@x>"));
+        assert_eq!(pdf.md5.as_deref(), Some("md5sum"));
+        assert!(pdf.is_attachment());
+        assert_eq!(pdf.filename(), Some("Grüße.pdf"));
+        assert_eq!(pdf.content_type.param("name"), Some("Büro.pdf"));
+        assert_eq!(pdf.decoded_size(), 3000);
+
+        let message = &body.parts[2];
+        assert_eq!(message.lines, Some(12));
+        assert_eq!(
+            message.envelope.as_ref().unwrap().subject.as_deref(),
+            Some("inner")
+        );
+        assert_eq!(message.parts[0].lines, Some(5));
+        assert_eq!(body.leaves().len(), 3);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(BodyStructure::parse(b"(\"text\" \"plain\" NIL)").is_err());
+        assert!(BodyStructure::parse(b"(\"text\" \"plain\" NIL NIL NIL \"7bit\" x)").is_err());
+        assert!(BodyStructure::parse(b"((\"text\" \"plain\" NIL NIL NIL \"7bit\" 1))").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::mail::imap::{parse_imap_value, ImapValue};
+use crate::mail::{decode_encoded_words, parse_date, Address, Mailbox};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset};
+
+/// An IMAP ENVELOPE as described in RFC 3501, the parsed addressing headers of a message.
+/// Strings are decoded from encoded words, NIL values are None or empty address lists.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The Date header as sent
+    pub date: Option<String>,
+    pub subject: Option<String>,
+    pub from: Vec<Address>,
+    pub sender: Vec<Address>,
+    pub reply_to: Vec<Address>,
+    pub to: Vec<Address>,
+    pub cc: Vec<Address>,
+    pub bcc: Vec<Address>,
+    pub in_reply_to: Option<String>,
+    pub message_id: Option<String>,
+}
+
+impl Envelope {
+    /// Parse an ENVELOPE as printed by doveadm fetch imap.envelope
+    pub fn parse(value: &[u8]) -> Result<Envelope> {
+        Envelope::from_value(&parse_imap_value(value)?)
+    }
+
+    pub(crate) fn from_value(value: &ImapValue) -> Result<Envelope> {
+        let list = value
+            .as_list()
+            .ok_or_else(|| anyhow!("envelope: expected a list, found {:?}", value))?;
+        if list.len() != 10 {
+            return Err(anyhow!(
+                "envelope: expected 10 fields, found {}",
+                list.len()
+            ));
+        }
+        Ok(Envelope {
+            date: opt_string(&list[0]),
+            subject: opt_string(&list[1]).map(|subject| decode_encoded_words(&subject)),
+            from: addresses(&list[2])?,
+            sender: addresses(&list[3])?,
+            reply_to: addresses(&list[4])?,
+            to: addresses(&list[5])?,
+            cc: addresses(&list[6])?,
+            bcc: addresses(&list[7])?,
+            in_reply_to: opt_string(&list[8]),
+            message_id: opt_string(&list[9]),
+        })
+    }
+
+    /// The parsed date, None if missing or invalid
+    pub fn parsed_date(&self) -> Option<DateTime<FixedOffset>> {
+        parse_date(self.date.as_deref()?)
+    }
+
+    /// The first From mailbox
+    pub fn from_mailbox(&self) -> Option<&Mailbox> {
+        self.from.iter().flat_map(Address::mailboxes).next()
+    }
+}
+
+fn opt_string(value: &ImapValue) -> Option<String> {
+    value.as_str().map(|value| value.to_owned())
+}
+
+// a list of (name adl mailbox host) entries or NIL. Groups start with an entry with a NIL host
+// and the group name as mailbox and end with an entry with NIL mailbox and host.
+fn addresses(value: &ImapValue) -> Result<Vec<Address>> {
+    let list = match value {
+        ImapValue::Nil => return Ok(Vec::new()),
+        ImapValue::List(list) => list,
+        value => {
+            return Err(anyhow!(
+                "envelope: expected an address list, found {:?}",
+                value
+            ))
+        }
+    };
+    let mut res = Vec::new();
+    let mut group: Option<(String, Vec<Mailbox>)> = None;
+    for entry in list {
+        let fields = match entry.as_list() {
+            Some(fields) if fields.len() == 4 => fields,
+            _ => return Err(anyhow!("envelope: invalid address {:?}", entry)),
+        };
+        match (fields[2].as_str(), fields[3].as_str()) {
+            (Some(name), None) => {
+                // start of group
+                if let Some((name, members)) = group.take() {
+                    res.push(Address::Group(name, members));
+                }
+                group = Some((decode_encoded_words(name), Vec::new()));
+            }
+            (None, None) => {
+                // end of group
+                if let Some((name, members)) = group.take() {
+                    res.push(Address::Group(name, members));
+                }
+            }
+            (local_part, domain) => {
+                let mailbox = Mailbox {
+                    display_name: fields[0]
+                        .as_str()
+                        .map(decode_encoded_words)
+                        .filter(|name| !name.is_empty()),
+                    local_part: local_part.unwrap_or_default().to_owned(),
+                    domain: domain.unwrap_or_default().to_owned(),
+                };
+                match group.as_mut() {
+                    Some((_, members)) => members.push(mailbox),
+                    None => res.push(Address::Mailbox(mailbox)),
+                }
+            }
+        }
+    }
+    if let Some((name, members)) = group {
+        res.push(Address::Group(name, members));
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let envelope = Envelope::parse(
+            b"(\"Mon, 15 Aug 2022 10:23:44 +0200\" \"=?UTF-8?Q?Gr=C3=BC=C3=9Fe?=\" \
+              ((\"=?UTF-8?Q?J=C3=B6rg?=\" NIL \"joerg\" \"example.de\")) \
+              ((\"=?UTF-8?Q?J=C3=B6rg?=\" NIL \"joerg\" \"example.de\")) \
+              ((NIL NIL \"joerg\" \"example.de\")) \
+              ((NIL NIL \"team\" NIL)(NIL NIL \"a\" \"example.com\")(NIL NIL NIL NIL)\
+               (\"Bob\" NIL \"bob\" \"example.org\")) \
+              NIL NIL NIL \"<1@example.de>\")",
+        )
+        .unwrap();
+        assert_eq!(envelope.subject.as_deref(), Some("Grüße"));
+        assert_eq!(
+            envelope.parsed_date().unwrap().to_rfc3339(),
+            "2022-08-15T10:23:44+02:00"
+        );
+        assert_eq!(
+            envelope.from_mailbox().unwrap().to_string(),
+            "Jörg <joerg@example.de>"
+        );
+        assert_eq!(envelope.to.len(), 2);
+        assert_eq!(envelope.to[0].mailboxes()[0].addr_spec(), "a@example.com");
+        assert!(matches!(&envelope.to[0], Address::Group(name, _) if name == "team"));
+        assert_eq!(
+            envelope.to[1].mailboxes()[0].display_name.as_deref(),
+            Some("Bob")
+        );
+        assert!(envelope.cc.is_empty());
+        assert_eq!(envelope.in_reply_to, None);
+        assert_eq!(envelope.message_id.as_deref(), Some("<1@example.de>"));
+    }
+
+    #[test]
+    fn test_literals() {
+        let envelope =
+            Envelope::parse(b"(NIL {6}\r\nHi \"x\" NIL NIL NIL NIL NIL NIL NIL NIL)").unwrap();
+        assert_eq!(envelope.subject.as_deref(), Some("Hi \"x\""));
+        assert_eq!(envelope.date, None);
+        assert!(envelope.from.is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Envelope::parse(b"(NIL NIL)").is_err());
+        assert!(Envelope::parse(b"(NIL NIL (\"x\") NIL NIL NIL NIL NIL NIL NIL)").is_err());
+    }
+}
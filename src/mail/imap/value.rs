@@ -0,0 +1,192 @@
+use crate::mail::decode_8bit;
+use anyhow::{anyhow, Result};
+
+/// A value of an IMAP response structure like BODYSTRUCTURE or ENVELOPE
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImapValue {
+    Nil,
+    // atoms and numbers
+    Atom(String),
+    // quoted strings and literals
+    String(String),
+    List(Vec<ImapValue>),
+}
+
+impl ImapValue {
+    /// The value of strings and atoms, None for NIL and lists
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ImapValue::Atom(value) | ImapValue::String(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<u64> {
+        self.as_str()?.parse::<u64>().ok()
+    }
+
+    pub fn as_list(&self) -> Option<&[ImapValue]> {
+        match self {
+            ImapValue::List(list) => Some(list.as_slice()),
+            _ => None,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        *self == ImapValue::Nil
+    }
+}
+
+/// Parse a single IMAP value - a parenthesized list, a quoted string, a literal ({n} followed by
+/// a line break and n bytes), NIL or an atom. Trailing whitespace is ignored.
+pub fn parse_imap_value(input: &[u8]) -> Result<ImapValue> {
+    let mut parser = ValueParser { input, pos: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < input.len() {
+        Err(anyhow!(
+            "imap value: unexpected data after value at position {}",
+            parser.pos + 1
+        ))
+    } else {
+        Ok(value)
+    }
+}
+
+struct ValueParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl ValueParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|ch| ch.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, msg: &str) -> anyhow::Error {
+        anyhow!("imap value: {} at position {}", msg, self.pos + 1)
+    }
+
+    fn parse_value(&mut self) -> Result<ImapValue> {
+        self.skip_whitespace();
+        match self.input.get(self.pos) {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'(') => {
+                self.pos += 1;
+                let mut list = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.input.get(self.pos) {
+                        Some(b')') => {
+                            self.pos += 1;
+                            return Ok(ImapValue::List(list));
+                        }
+                        None => return Err(self.error("unterminated list")),
+                        Some(_) => list.push(self.parse_value()?),
+                    }
+                }
+            }
+            Some(b')') => Err(self.error("unexpected ')'")),
+            Some(b'"') => {
+                self.pos += 1;
+                let mut value = Vec::new();
+                loop {
+                    match self.input.get(self.pos) {
+                        Some(b'"') => {
+                            self.pos += 1;
+                            return Ok(ImapValue::String(decode_8bit(&value)));
+                        }
+                        Some(b'\\') if self.pos + 1 < self.input.len() => {
+                            value.push(self.input[self.pos + 1]);
+                            self.pos += 2;
+                        }
+                        Some(ch) => {
+                            value.push(*ch);
+                            self.pos += 1;
+                        }
+                        None => return Err(self.error("unterminated quoted string")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                let end = self.input[self.pos..]
+                    .iter()
+                    .position(|ch| *ch == b'}')
+                    .map(|end| self.pos + end)
+                    .ok_or_else(|| self.error("unterminated literal size"))?;
+                let size = std::str::from_utf8(&self.input[self.pos + 1..end])
+                    .ok()
+                    .map(|size| size.trim_end_matches('+'))
+                    .and_then(|size| size.parse::<usize>().ok())
+                    .ok_or_else(|| self.error("invalid literal size"))?;
+                self.pos = end + 1;
+                if self.input.get(self.pos) == Some(&b'\r') {
+                    self.pos += 1;
+                }
+                if self.input.get(self.pos) != Some(&b'\n') {
+                    return Err(self.error("expected line break after literal size"));
+                }
+                self.pos += 1;
+                if self.pos + size > self.input.len() {
+                    return Err(self.error("literal exceeds input"));
+                }
+                let value = decode_8bit(&self.input[self.pos..self.pos + size]);
+                self.pos += size;
+                Ok(ImapValue::String(value))
+            }
+            Some(_) => {
+                let start = self.pos;
+                while self.input.get(self.pos).is_some_and(|ch| {
+                    !ch.is_ascii_whitespace() && !matches!(ch, b'(' | b')' | b'"' | b'{')
+                }) {
+                    self.pos += 1;
+                }
+                let atom = decode_8bit(&self.input[start..self.pos]);
+                if atom.eq_ignore_ascii_case("NIL") {
+                    Ok(ImapValue::Nil)
+                } else {
+                    Ok(ImapValue::Atom(atom))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> ImapValue {
+        ImapValue::String(value.to_owned())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_imap_value(b"(\"a \\\"b\\\"\" NIL 42 (x) {5}\r\nhe)lo)\n").unwrap(),
+            ImapValue::List(vec![
+                string("a \"b\""),
+                ImapValue::Nil,
+                ImapValue::Atom("42".to_owned()),
+                ImapValue::List(vec![ImapValue::Atom("x".to_owned())]),
+                string("he)lo"),
+            ])
+        );
+        assert_eq!(parse_imap_value(b"()").unwrap(), ImapValue::List(vec![]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_imap_value(b"(a b").is_err());
+        assert!(parse_imap_value(b"\"abc").is_err());
+        assert!(parse_imap_value(b"{10}\nabc").is_err());
+        assert!(parse_imap_value(b"(a) b").is_err());
+        assert!(parse_imap_value(b"").is_err());
+    }
+}
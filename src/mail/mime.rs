@@ -0,0 +1,333 @@
+use crate::mail::{decode_8bit, decode_charset, Header};
+
+mod params;
+pub use params::ParamValue;
+
+mod transfer;
+pub use transfer::{decode_base64, decode_quoted_printable, decode_transfer_encoding};
+
+const HDR_CONTENT_TYPE: &str = "content-type";
+const HDR_CONTENT_DISPOSITION: &str = "content-disposition";
+const HDR_CONTENT_TRANSFER_ENCODING: &str = "content-transfer-encoding";
+const DEFAULT_CONTENT_TYPE: &str = "text/plain";
+const DEFAULT_CHARSET: &str = "us-ascii";
+// nesting deeper than this is not parsed, the body is kept as a single part
+const MAX_DEPTH: usize = 32;
+
+/// A MIME part with its body decoded from the transfer encoding. Multipart and message/rfc822
+/// parts contain their sub parts in parts, their body is empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MimePart {
+    /// The headers of the part, those of the message for the root part
+    pub headers: Vec<Header>,
+    /// The Content-Type, text/plain if not given
+    pub content_type: ParamValue,
+    /// The Content-Disposition if given
+    pub disposition: Option<ParamValue>,
+    /// The Content-Transfer-Encoding, lowercased, 7bit if not given
+    pub encoding: String,
+    /// The decoded body
+    pub body: Vec<u8>,
+    pub parts: Vec<MimePart>,
+}
+
+impl MimePart {
+    /// Parse a complete message - headers and body
+    pub fn parse(message: &[u8]) -> MimePart {
+        MimePart::parse_part(message, DEFAULT_CONTENT_TYPE, 0)
+    }
+
+    /// Parse a message body given the message headers, which were fetched separately
+    pub fn parse_body(headers: Vec<Header>, body: &[u8]) -> MimePart {
+        MimePart::from_headers(headers, body, DEFAULT_CONTENT_TYPE, 0)
+    }
+
+    fn parse_part(part: &[u8], default_type: &str, depth: usize) -> MimePart {
+        let (headers, body) = split_headers(part);
+        MimePart::from_headers(headers, body, default_type, depth)
+    }
+
+    fn from_headers(
+        headers: Vec<Header>,
+        body: &[u8],
+        default_type: &str,
+        depth: usize,
+    ) -> MimePart {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .map(|header| header.value.as_str())
+        };
+        let mut content_type = ParamValue::parse(find(HDR_CONTENT_TYPE).unwrap_or(default_type));
+        if !content_type.value.contains('/') {
+            // RFC 2045: invalid content types are treated as the default
+            content_type = ParamValue::parse(default_type);
+        }
+        let disposition = find(HDR_CONTENT_DISPOSITION).map(ParamValue::parse);
+        let encoding = find(HDR_CONTENT_TRANSFER_ENCODING)
+            .map(|value| value.trim().to_lowercase())
+            .unwrap_or_else(|| "7bit".to_owned());
+
+        let mut part = MimePart {
+            headers,
+            content_type,
+            disposition,
+            encoding,
+            body: Vec::new(),
+            parts: Vec::new(),
+        };
+
+        let boundary = part
+            .content_type
+            .param("boundary")
+            .filter(|boundary| !boundary.is_empty())
+            .map(|boundary| boundary.to_owned());
+        match boundary {
+            Some(boundary) if part.is_multipart() && depth < MAX_DEPTH => {
+                // parts of multipart/digest default to message/rfc822
+                let default_type = if part.content_type.value == "multipart/digest" {
+                    "message/rfc822"
+                } else {
+                    DEFAULT_CONTENT_TYPE
+                };
+                part.parts = split_multipart(body, &boundary)
+                    .into_iter()
+                    .map(|sub_part| MimePart::parse_part(sub_part, default_type, depth + 1))
+                    .collect();
+            }
+            _ if part.content_type.value == "message/rfc822" && depth < MAX_DEPTH => {
+                let message = decode_transfer_encoding(body, &part.encoding);
+                part.parts = vec![MimePart::parse_part(
+                    &message,
+                    DEFAULT_CONTENT_TYPE,
+                    depth + 1,
+                )];
+            }
+            _ => part.body = decode_transfer_encoding(body, &part.encoding),
+        }
+        part
+    }
+
+    pub fn is_multipart(&self) -> bool {
+        self.content_type.value.starts_with("multipart/")
+    }
+
+    /// The charset of a text part, us-ascii if not given
+    pub fn charset(&self) -> &str {
+        self.content_type
+            .param("charset")
+            .unwrap_or(DEFAULT_CHARSET)
+    }
+
+    /// The file name from the Content-Disposition or the Content-Type name parameter
+    pub fn filename(&self) -> Option<&str> {
+        self.disposition
+            .as_ref()
+            .and_then(|disposition| disposition.param("filename"))
+            .or_else(|| self.content_type.param("name"))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Parts with an attachment disposition or a file name
+    pub fn is_attachment(&self) -> bool {
+        !self.is_multipart()
+            && (self
+                .disposition
+                .as_ref()
+                .is_some_and(|disposition| disposition.value == "attachment")
+                || self.filename().is_some())
+    }
+
+    /// The body of a text part converted from its charset
+    pub fn text(&self) -> Option<String> {
+        if self.content_type.value.starts_with("text/") {
+            Some(decode_charset(&self.body, self.charset()))
+        } else {
+            None
+        }
+    }
+
+    /// The decoded size of the part, for multipart parts the sum of the sub parts
+    pub fn decoded_size(&self) -> usize {
+        self.body.len()
+            + self
+                .parts
+                .iter()
+                .map(|part| part.decoded_size())
+                .sum::<usize>()
+    }
+
+    /// All parts of the tree that have no sub parts, depth first
+    pub fn leaves(&self) -> Vec<&MimePart> {
+        if self.parts.is_empty() {
+            vec![self]
+        } else {
+            self.parts.iter().flat_map(|part| part.leaves()).collect()
+        }
+    }
+}
+
+// split a part at the empty line terminating the header block
+fn split_headers(part: &[u8]) -> (Vec<Header>, &[u8]) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut pos = 0;
+    while pos < part.len() {
+        let end = part[pos..]
+            .iter()
+            .position(|ch| *ch == b'\n')
+            .map_or(part.len(), |end| pos + end + 1);
+        let line = trim_line_end(&part[pos..end]);
+        pos = end;
+        if line.is_empty() {
+            break;
+        } else if line.starts_with(b" ") || line.starts_with(b"\t") {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push('\n');
+                value.push_str(&decode_8bit(line));
+            }
+        } else if let Some(colon) = line.iter().position(|ch| *ch == b':') {
+            headers.push((
+                decode_8bit(&line[..colon]).trim().to_owned(),
+                decode_8bit(&line[colon + 1..]).trim_start().to_owned(),
+            ));
+        } else {
+            // not a header, there is no header block
+            return (Vec::new(), part);
+        }
+    }
+    (
+        headers
+            .into_iter()
+            .map(|(name, value)| Header::new(name, value))
+            .collect(),
+        &part[pos..],
+    )
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// the parts between the boundary delimiter lines, preamble and epilogue are dropped. The line
+// break preceding a delimiter belongs to the delimiter.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        let end = body[pos..]
+            .iter()
+            .position(|ch| *ch == b'\n')
+            .map_or(body.len(), |end| pos + end + 1);
+        let line = trim_line_end(&body[pos..end]);
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes()) {
+            let is_close = rest.starts_with(b"--");
+            if is_close || rest.iter().all(|ch| ch.is_ascii_whitespace()) {
+                if let Some(start) = start {
+                    let part = &body[start..pos.max(start)];
+                    let part = part.strip_suffix(b"\n").unwrap_or(part);
+                    parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+                }
+                if is_close {
+                    return parts;
+                }
+                start = Some(end);
+            }
+        }
+        pos = end;
+    }
+    // a missing close delimiter ends the last part at the end of the body
+    if let Some(start) = start {
+        parts.push(&body[start.min(body.len())..]);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"From: alice@example.com\r\n\
+Subject: test\r\n\
+Content-Type: multipart/mixed;\r\n boundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=iso-8859-1\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Gr=FC=DFe\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<p>Gr\xc3\xbc\xc3\x9fe</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf; name=\"report.pdf\"\r\n\
+Content-Disposition: attachment\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0xLjQK\r\n\
+--outer\r\n\
+Content-Type: message/rfc822\r\n\
+\r\n\
+Subject: inner\r\n\
+\r\n\
+forwarded\r\n\
+--outer--\r\n\
+epilogue\r\n";
+
+    #[test]
+    fn test_parse_tree() {
+        let message = MimePart::parse(MESSAGE);
+        assert_eq!(message.content_type.value, "multipart/mixed");
+        assert_eq!(message.headers.len(), 3);
+        assert_eq!(message.parts.len(), 3);
+
+        let alternative = &message.parts[0];
+        assert_eq!(alternative.content_type.value, "multipart/alternative");
+        assert_eq!(alternative.parts.len(), 2);
+        assert_eq!(alternative.parts[0].text().as_deref(), Some("Grüße"));
+        assert_eq!(alternative.parts[1].text().as_deref(), Some("<p>Grüße</p>"));
+
+        let pdf = &message.parts[1];
+        assert!(pdf.is_attachment());
+        assert_eq!(pdf.filename(), Some("report.pdf"));
+        assert_eq!(pdf.body, b"%PDF-1.4\n");
+        assert_eq!(pdf.decoded_size(), 9);
+
+        let forwarded = &message.parts[2];
+        assert_eq!(forwarded.parts.len(), 1);
+        assert_eq!(forwarded.parts[0].text().as_deref(), Some("forwarded"));
+
+        assert_eq!(message.leaves().len(), 4);
+        assert_eq!(
+            message.decoded_size(),
+            "Grüße".len() - 2 + "<p>Grüße</p>".len() + 9 + "forwarded".len()
+        );
+    }
+
+    #[test]
+    fn test_single_part() {
+        let message = MimePart::parse(b"Subject: plain\n\nline 1\nline 2\n");
+        assert_eq!(message.content_type.value, "text/plain");
+        assert_eq!(message.charset(), "us-ascii");
+        assert_eq!(message.body, b"line 1\nline 2\n");
+        assert!(!message.is_attachment());
+
+        let headers = vec![Header::new(
+            "Content-Type".to_owned(),
+            "multipart/mixed; boundary=b".to_owned(),
+        )];
+        let message = MimePart::parse_body(headers, b"--b\n\nfirst\n--b\n\nsecond\n");
+        // a missing close delimiter ends the last part at the end of the body
+        assert_eq!(message.parts.len(), 2);
+        assert_eq!(message.parts[1].body, b"second\n");
+    }
+}
@@ -0,0 +1,237 @@
+use crate::mail::{decode_charset, decode_encoded_words};
+
+/// A header value with parameters like Content-Type or Content-Disposition,
+/// eg. 'text/plain; charset="utf-8"'
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParamValue {
+    /// The value, lowercased
+    pub value: String,
+    /// Parameters with lowercased names and decoded values
+    pub params: Vec<(String, String)>,
+}
+
+impl ParamValue {
+    /// Parse an unfolded header value. RFC 2231 continuations and extended values are joined and
+    /// decoded, encoded words are decoded as some mailers use them in file names.
+    pub fn parse(value: &str) -> ParamValue {
+        let mut parts = split_params(value).into_iter();
+        let main = parts.next().unwrap_or_default();
+        ParamValue::from_params(
+            &main,
+            parts.filter_map(|part| {
+                part.split_once('=')
+                    .map(|(name, value)| (name.to_owned(), unquote(value.trim())))
+            }),
+        )
+    }
+
+    /// Create from a value and raw parameters given as name value pairs, like those of an IMAP
+    /// BODYSTRUCTURE. Parameters are decoded as by parse.
+    pub fn from_params<I: IntoIterator<Item = (String, String)>>(
+        value: &str,
+        raw: I,
+    ) -> ParamValue {
+        // (name, section, extended, value) in the order found
+        let mut raw_params: Vec<(String, Option<usize>, bool, String)> = Vec::new();
+        for (name, value) in raw {
+            let name = name.trim().to_lowercase();
+            let (name, extended) = match name.strip_suffix('*') {
+                Some(name) => (name.to_owned(), true),
+                None => (name, false),
+            };
+            let (name, section) = match name.rsplit_once('*') {
+                Some((base, section)) if section.parse::<usize>().is_ok() => {
+                    (base.to_owned(), section.parse::<usize>().ok())
+                }
+                _ => (name, None),
+            };
+            raw_params.push((name, section, extended, value));
+        }
+
+        let mut params: Vec<(String, String)> = Vec::new();
+        let mut done: Vec<&str> = Vec::new();
+        for (name, section, _, _) in &raw_params {
+            if done.contains(&name.as_str()) {
+                continue;
+            }
+            done.push(name.as_str());
+            let value = if section.is_some() {
+                let mut sections: Vec<&(String, Option<usize>, bool, String)> = raw_params
+                    .iter()
+                    .filter(|(curr, section, _, _)| curr == name && section.is_some())
+                    .collect();
+                sections.sort_by_key(|(_, section, _, _)| *section);
+                decode_sections(&sections)
+            } else {
+                let (_, _, extended, value) = raw_params
+                    .iter()
+                    .find(|(curr, section, _, _)| curr == name && section.is_none())
+                    .expect("parameter without section not found");
+                if *extended {
+                    decode_extended(value)
+                } else {
+                    decode_encoded_words(value)
+                }
+            };
+            params.push((name.clone(), value));
+        }
+
+        ParamValue {
+            value: value.trim().to_lowercase(),
+            params,
+        }
+    }
+
+    /// The value of the parameter name, the lookup is case insensitive
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(curr, _)| curr.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// split at semicolons outside of quoted strings
+fn split_params(value: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut curr = String::new();
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                curr.push(ch);
+            }
+            '\\' if quoted => {
+                curr.push(ch);
+                curr.extend(chars.next());
+            }
+            ';' if !quoted => res.push(std::mem::take(&mut curr)),
+            ch => curr.push(ch),
+        }
+    }
+    res.push(curr);
+    res
+}
+
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"') {
+        Some(inner) => {
+            let inner = inner.strip_suffix('"').unwrap_or(inner);
+            let mut res = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(ch) = chars.next() {
+                if ch == '\\' {
+                    res.extend(chars.next());
+                } else {
+                    res.push(ch);
+                }
+            }
+            res
+        }
+        None => value.to_owned(),
+    }
+}
+
+// join the sections of a continued parameter, the charset is given in the first extended section
+fn decode_sections(sections: &[&(String, Option<usize>, bool, String)]) -> String {
+    let mut charset: Option<String> = None;
+    let mut bytes: Vec<u8> = Vec::new();
+    for (idx, (_, _, extended, value)) in sections.iter().enumerate() {
+        if *extended {
+            let value = if idx == 0 {
+                match split_extended(value) {
+                    Some((curr_charset, value)) => {
+                        charset = Some(curr_charset.to_owned());
+                        value
+                    }
+                    None => value.as_str(),
+                }
+            } else {
+                value.as_str()
+            };
+            bytes.append(&mut percent_decode(value));
+        } else {
+            bytes.extend_from_slice(value.as_bytes());
+        }
+    }
+    match charset {
+        Some(charset) => decode_charset(&bytes, &charset),
+        None => decode_encoded_words(&String::from_utf8_lossy(&bytes)),
+    }
+}
+
+// charset'language'percent-encoded-value, values without charset are taken as UTF-8
+fn decode_extended(value: &str) -> String {
+    match split_extended(value) {
+        Some((charset, value)) => decode_charset(&percent_decode(value), charset),
+        None => decode_charset(&percent_decode(value), "utf-8"),
+    }
+}
+
+fn split_extended(value: &str) -> Option<(&str, &str)> {
+    let (charset, rest) = value.split_once('\'')?;
+    let (_language, value) = rest.split_once('\'')?;
+    Some((
+        if charset.is_empty() {
+            "us-ascii"
+        } else {
+            charset
+        },
+        value,
+    ))
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' && idx + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[idx + 1..idx + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                res.push(byte);
+                idx += 3;
+                continue;
+            }
+        }
+        res.push(bytes[idx]);
+        idx += 1;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value = ParamValue::parse("Text/Plain; charset=\"UTF-8\"; format=flowed");
+        assert_eq!(value.value, "text/plain");
+        assert_eq!(value.param("Charset"), Some("UTF-8"));
+        assert_eq!(value.param("format"), Some("flowed"));
+        assert_eq!(value.param("delsp"), None);
+
+        let value = ParamValue::parse("attachment; filename=\"a; \\\"b\\\".pdf\"");
+        assert_eq!(value.value, "attachment");
+        assert_eq!(value.param("filename"), Some("a; \"b\".pdf"));
+    }
+
+    #[test]
+    fn test_rfc2231() {
+        let value = ParamValue::parse("attachment; filename*=iso-8859-1'de'Gr%FC%DFe.txt");
+        assert_eq!(value.param("filename"), Some("Grüße.txt"));
+
+        let value = ParamValue::parse(
+            "application/pdf; name*0*=utf-8''Gr%C3%BC; name*1=\"sse und \"; name*2*=%C3%BC.pdf",
+        );
+        assert_eq!(value.param("name"), Some("Grüsse und ü.pdf"));
+
+        let value = ParamValue::parse("image/png; name=\"=?UTF-8?Q?B=C3=BCro.png?=\"");
+        assert_eq!(value.param("name"), Some("Büro.png"));
+    }
+}
@@ -0,0 +1,120 @@
+use base64::alphabet::STANDARD;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+
+// the base64 engine of all decoders, lenient as mailers frequently omit padding
+pub(crate) const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// Decode a body in the given Content-Transfer-Encoding. Unknown encodings and identity
+/// encodings (7bit, 8bit, binary) are returned as they are.
+pub fn decode_transfer_encoding(body: &[u8], encoding: &str) -> Vec<u8> {
+    match encoding.trim().to_lowercase().as_str() {
+        "base64" => decode_base64(body),
+        "quoted-printable" => decode_quoted_printable(body),
+        _ => body.to_vec(),
+    }
+}
+
+/// Decode a base64 body, characters outside of the alphabet like line breaks are ignored as
+/// required by RFC 2045. Decoding stops at the first undecodable chunk.
+pub fn decode_base64(body: &[u8]) -> Vec<u8> {
+    let clean: Vec<u8> = body
+        .iter()
+        .copied()
+        .filter(|ch| ch.is_ascii_alphanumeric() || *ch == b'+' || *ch == b'/')
+        .collect();
+    // a single trailing character does not encode a byte
+    let len = if clean.len() % 4 == 1 {
+        clean.len() - 1
+    } else {
+        clean.len()
+    };
+    match BASE64.decode(&clean[..len]) {
+        Ok(res) => res,
+        Err(_) => {
+            // decode what can be decoded in chunks of 4 characters
+            let mut res = Vec::with_capacity(len / 4 * 3);
+            for chunk in clean[..len].chunks(4) {
+                match BASE64.decode(chunk) {
+                    Ok(mut bytes) => res.append(&mut bytes),
+                    Err(_) => break,
+                }
+            }
+            res
+        }
+    }
+}
+
+/// Decode a quoted-printable body, soft line breaks are removed and malformed escapes are kept
+/// as they are
+pub fn decode_quoted_printable(body: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(body.len());
+    let mut idx = 0;
+    while idx < body.len() {
+        let ch = body[idx];
+        if ch == b'=' {
+            let rest = &body[idx + 1..];
+            if rest.starts_with(b"\r\n") {
+                idx += 3;
+                continue;
+            } else if rest.starts_with(b"\n") {
+                idx += 2;
+                continue;
+            } else if rest.len() >= 2 {
+                if let (Some(high), Some(low)) = (hex_value(rest[0]), hex_value(rest[1])) {
+                    res.push(high << 4 | low);
+                    idx += 3;
+                    continue;
+                }
+            }
+            // soft line break with trailing whitespace
+            let trailing = rest
+                .iter()
+                .take_while(|ch| **ch == b' ' || **ch == b'\t' || **ch == b'\r')
+                .count();
+            if rest.get(trailing) == Some(&b'\n') {
+                idx += trailing + 2;
+                continue;
+            }
+        }
+        res.push(ch);
+        idx += 1;
+    }
+    res
+}
+
+pub(crate) fn hex_value(ch: u8) -> Option<u8> {
+    match ch {
+        b'0'..=b'9' => Some(ch - b'0'),
+        b'A'..=b'F' => Some(ch - b'A' + 10),
+        b'a'..=b'f' => Some(ch - b'a' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(decode_base64(b"SGVsbG8g\r\nV29ybGQ=\r\n"), b"Hello World");
+        assert_eq!(decode_base64(b"SGVsbG8"), b"Hello");
+        assert_eq!(decode_transfer_encoding(b"SGk=", " Base64"), b"Hi");
+    }
+
+    #[test]
+    fn test_quoted_printable() {
+        assert_eq!(
+            decode_quoted_printable(b"Gr=C3=BC=C3=9Fe =\r\naus=20M=FCnchen=  \nfoo =XY"),
+            b"Gr\xc3\xbc\xc3\x9fe aus M\xfcnchenfoo =XY"
+        );
+        assert_eq!(decode_transfer_encoding(b"a=3Db", "7bit"), b"a=3Db");
+    }
+}
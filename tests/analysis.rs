@@ -0,0 +1,216 @@
+use chrono::DateTime;
+use mail_kraken::analysis::{
+    AgeReport, AttachmentReport, DuplicateReport, ListReport, Report, SizeReport,
+};
+
+mod common;
+use common::run_report;
+
+#[test]
+fn attachments() {
+    let report = run_report("attachments", AttachmentReport::new(0));
+    let attachments = report.attachments();
+    assert_eq!(attachments.len(), 3);
+
+    // ordered by user, mailbox and UID
+    let pdf = attachments[0];
+    assert_eq!(pdf.user, "alice@example.com");
+    assert_eq!((pdf.mailbox.as_str(), pdf.uid), ("INBOX", 3));
+    assert_eq!(pdf.sender, "alice@example.com");
+    assert_eq!(pdf.mime_type, "application/pdf");
+    assert_eq!(pdf.filename.as_deref(), Some("report.pdf"));
+    assert_eq!((pdf.size, pdf.decoded_size), (40000, 30000));
+    // an attachment disposition without a file name
+    assert_eq!(attachments[1].filename, None);
+    assert_eq!(attachments[1].mime_type, "application/zip");
+
+    let logo = attachments[2];
+    assert_eq!(logo.user, "bob@example.com");
+    assert_eq!((logo.mailbox.as_str(), logo.uid), ("Archive", 1));
+    assert_eq!(logo.filename.as_deref(), Some("logo.png"));
+    assert_eq!(logo.sender, "carol@example.net");
+
+    let by_type = report.by_type();
+    assert_eq!(by_type.len(), 3);
+    assert_eq!(by_type[0].0, "application/pdf");
+    let by_sender = report.by_sender();
+    assert_eq!(by_sender[0].0, "alice@example.com");
+    assert_eq!(by_sender[0].1.count, 2);
+    assert_eq!(by_sender[0].1.size, 52000);
+    let by_year: Vec<&str> = report
+        .by_year()
+        .iter()
+        .map(|(year, _)| year.as_str())
+        .collect();
+    assert_eq!(by_year, vec!["2021", "2022"]);
+    assert_eq!(report.messages(), (2, 65000));
+
+    let output = report.to_string();
+    assert!(output.contains("report.pdf"));
+    assert!(output.contains("3 attachments in 2 messages of 65000 bytes"));
+
+    // the roll up keeps the totals only
+    let mut rollup = report.new_rollup();
+    report.roll_up(&mut rollup);
+    report.roll_up(&mut rollup);
+    assert_eq!(rollup.messages(), (4, 130000));
+    assert_eq!(rollup.by_type()[0].1.count, 2);
+    let output = rollup.to_string();
+    assert!(!output.contains("report.pdf"));
+    assert!(output.contains("6 attachments in 4 messages of 130000 bytes"));
+}
+
+#[test]
+fn attachments_min_size() {
+    let report = run_report("attachments", AttachmentReport::new(10000));
+    let attachments = report.attachments();
+    assert_eq!(attachments.len(), 2);
+    assert!(attachments.iter().all(|att| att.mailbox == "INBOX"));
+    assert_eq!(report.messages(), (1, 60000));
+}
+
+#[test]
+fn duplicates_by_message_id() {
+    let report = run_report("duplicates", DuplicateReport::new(false));
+    let duplicates = report.duplicates();
+    assert_eq!(duplicates.len(), 1);
+
+    // the domain of the Message-ID is compared case insensitively
+    let set = &duplicates[0];
+    assert_eq!(set.message_id.as_deref(), Some("A1@example.com"));
+    assert_eq!(set.hash, None);
+    let locations: Vec<(&str, u64)> = set
+        .messages
+        .iter()
+        .map(|msg| (msg.mailbox.as_str(), msg.uid))
+        .collect();
+    assert_eq!(locations, vec![("Archive", 7), ("INBOX", 1), ("Sent", 2)]);
+    assert_eq!(set.size(), 3200);
+    // the largest copy is kept
+    assert_eq!(set.reclaimable(), 2000);
+    assert_eq!(report.skipped(), 2);
+
+    let output = report.to_string();
+    assert!(output.contains("A1@example.com (3 copies, 2000 bytes reclaimable)"));
+    assert!(output.contains("2 messages without Message-ID ignored"));
+
+    // the roll up keeps the totals only
+    let mut rollup = report.new_rollup();
+    report.roll_up(&mut rollup);
+    report.roll_up(&mut rollup);
+    assert_eq!(
+        (
+            rollup.sets,
+            rollup.messages,
+            rollup.reclaimable,
+            rollup.skipped
+        ),
+        (2, 6, 4000, 4)
+    );
+    assert!(!rollup.to_string().contains("A1@example.com"));
+}
+
+#[test]
+fn duplicates_by_content_hash() {
+    let report = run_report("duplicateshash", DuplicateReport::new(true));
+    let duplicates = report.duplicates();
+    assert_eq!(duplicates.len(), 2);
+
+    // the copy in Sent has a different body, Received headers are not hashed
+    let set = &duplicates[0];
+    assert_eq!(set.message_id.as_deref(), Some("A1@example.com"));
+    assert!(set.hash.is_some());
+    let guids: Vec<&str> = set.messages.iter().map(|msg| msg.guid.as_str()).collect();
+    assert_eq!(guids, vec!["g2", "g1"]);
+    assert_eq!(set.reclaimable(), 1000);
+
+    // messages without a Message-ID are found by their hash
+    let set = &duplicates[1];
+    assert_eq!(set.message_id, None);
+    assert_eq!(set.messages.len(), 2);
+    assert_eq!(report.skipped(), 0);
+    assert_eq!(report.reclaimable(), 1300);
+}
+
+#[test]
+fn sizes() {
+    let report = run_report("sizes", SizeReport::new(2));
+    let totals = report.totals();
+    assert_eq!((totals.count, totals.size), (4, 4155000));
+
+    let classes: Vec<(&str, usize)> = report
+        .classes()
+        .iter()
+        .map(|(name, totals)| (*name, totals.count))
+        .collect();
+    assert_eq!(
+        classes,
+        vec![
+            ("< 10k", 1),
+            ("< 100k", 0),
+            ("< 1M", 1),
+            ("< 10M", 2),
+            (">= 10M", 0)
+        ]
+    );
+
+    // ties are ordered by user
+    let largest = report.largest();
+    assert_eq!(largest.len(), 2);
+    assert_eq!(
+        (largest[0].user.as_str(), largest[0].uid),
+        ("alice@example.com", 2)
+    );
+    assert_eq!(largest[0].subject.as_deref(), Some("Video"));
+    assert_eq!(
+        (largest[1].user.as_str(), largest[1].uid),
+        ("bob@example.com", 3)
+    );
+
+    assert!(report.to_string().contains("Fwd: Video"));
+}
+
+#[test]
+fn lists() {
+    let report = run_report("lists", ListReport::new());
+    let lists = report.sorted();
+    assert_eq!(lists.len(), 2);
+    assert_eq!(lists[0].0, "announce.example.com");
+    assert_eq!((lists[0].1.count, lists[0].1.unseen), (1, 0));
+
+    // the list id is compared case insensitively
+    let (id, stats) = lists[1];
+    assert_eq!(id, "users.rust-lang.org");
+    assert_eq!(stats.description.as_deref(), Some("Rust Users"));
+    assert_eq!((stats.count, stats.unseen, stats.size), (2, 1, 4000));
+    assert_eq!(
+        stats.last_seen.unwrap().format("%Y-%m-%d").to_string(),
+        "2022-08-16"
+    );
+    assert_eq!(report.others(), (1, 500));
+}
+
+#[test]
+fn age() {
+    let now = DateTime::parse_from_rfc3339("2022-09-01T00:00:00+00:00").unwrap();
+    let report = run_report("age", AgeReport::new(now));
+    let classes: Vec<(&str, usize, usize)> = report
+        .classes()
+        .iter()
+        .filter(|(_, totals)| totals.count > 0)
+        .map(|(name, totals)| (*name, totals.count, totals.unseen))
+        .collect();
+    assert_eq!(
+        classes,
+        vec![
+            ("< 1 month", 1, 0),
+            ("< 1 year", 1, 1),
+            ("< 5 years", 1, 0),
+            (">= 5 years", 1, 1)
+        ]
+    );
+    assert_eq!(
+        report.oldest().unwrap().format("%Y-%m-%d").to_string(),
+        "2015-01-01"
+    );
+}
@@ -0,0 +1,55 @@
+// helpers shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use mail_kraken::analysis::Report;
+use mail_kraken::doveadm::{DoveadmFetch, FetchParams, ImapField, SearchParam};
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+// replays tests/fixtures/<user>.<command>, see tests/fixtures/fake_doveadm
+pub const FAKE_DOVEADM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fake_doveadm");
+
+// the params to fetch fields of all messages in the fixture of user
+pub fn params(user: &str, fields: &[ImapField]) -> FetchParams {
+    let mut params = FetchParams::new(user.to_owned());
+    for field in fields {
+        params.add_field(field.clone());
+    }
+    params.add_search_param(SearchParam::All);
+    params
+}
+
+// runs report over the records of the fixture of user, fetching the fields it requires
+pub fn run_report<R: Report>(user: &str, mut report: R) -> R {
+    let params = params(user, &report.required_fields());
+    for record in DoveadmFetch::with_cmd(FAKE_DOVEADM, params).unwrap() {
+        report.add_record(&record.unwrap());
+    }
+    report
+}
+
+// a link to tests/fixtures/counting_doveadm in a directory of its own named after test, and the
+// log of the commands it runs. The directory of an earlier run is replaced.
+pub fn counting_doveadm(test: &str) -> (PathBuf, PathBuf) {
+    let dir = env::temp_dir().join(format!("mail_kraken-{}", test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cmd = dir.join("doveadm");
+    symlink(
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/counting_doveadm"
+        ),
+        &cmd,
+    )
+    .unwrap();
+    (cmd, dir.join("fetches.log"))
+}
+
+// the log lines of counting_doveadm, empty before the first fetch
+pub fn fetch_log(log: &Path) -> Vec<String> {
+    fs::read_to_string(log)
+        .map(|log| log.lines().map(|line| line.to_owned()).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,131 @@
+use mail_kraken::analysis::DuplicateReport;
+use mail_kraken::dedup::{plan_removals, DuplicateRemover, KeepPolicy, Removal, RemovalMethod};
+use mail_kraken::doveadm::{DoveadmCmd, DoveadmError, DoveadmErrorKind};
+
+mod common;
+use common::{run_report, FAKE_DOVEADM};
+
+// the removals for the duplicates fixture, which has one set of three copies
+fn removals(policy: &KeepPolicy) -> Vec<Removal> {
+    let report = run_report("duplicates", DuplicateReport::new(false));
+    plan_removals(&report.duplicates(), policy)
+}
+
+fn remover(user: &str, method: RemovalMethod, dry_run: bool) -> DuplicateRemover<Vec<u8>> {
+    let mut remover = DuplicateRemover::new(user.to_owned(), method, Vec::new());
+    remover
+        .set_cmd(DoveadmCmd::with_cmd(FAKE_DOVEADM))
+        .set_dry_run(dry_run);
+    remover
+}
+
+fn log_lines(remover: DuplicateRemover<Vec<u8>>) -> Vec<String> {
+    String::from_utf8(remover.into_log())
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+#[test]
+fn keep_policies() {
+    let kept = |policy: KeepPolicy| {
+        let removals = removals(&policy);
+        assert_eq!(removals.len(), 2);
+        removals[0].kept.guid.clone()
+    };
+    assert_eq!(kept(KeepPolicy::Oldest), "g3");
+    assert_eq!(kept(KeepPolicy::MostFlags), "g2");
+    assert_eq!(kept(KeepPolicy::Folder("INBOX".to_owned())), "g1");
+}
+
+#[test]
+fn dry_run() {
+    let removals = removals(&KeepPolicy::Oldest);
+    let mut remover = remover(
+        "duplicates",
+        RemovalMethod::Quarantine("Quarantine".to_owned()),
+        true,
+    );
+    let summary = remover.remove(&removals).unwrap();
+    assert_eq!(
+        (summary.removed, summary.size, summary.failed),
+        (2, 2000, 0)
+    );
+
+    // nothing was moved, so there is nothing to undo
+    let lines = log_lines(remover);
+    assert_eq!(
+        lines[0],
+        "dry-run\tmove:Quarantine\tArchive\tmb-archive\t7\tg2\t1000\tSent:2\t-"
+    );
+    assert!(lines[1].starts_with("dry-run\tmove:Quarantine\tINBOX\tmb-inbox\t1\tg1\t"));
+}
+
+#[test]
+fn quarantine_skips_quarantined_copies() {
+    let removals = removals(&KeepPolicy::Oldest);
+    let mut remover = remover(
+        "duplicates",
+        RemovalMethod::Quarantine("Archive".to_owned()),
+        false,
+    );
+    let summary = remover.remove(&removals).unwrap();
+    assert_eq!((summary.removed, summary.skipped), (1, 1));
+
+    let lines = log_lines(remover);
+    assert!(lines[0].starts_with("skipped: in quarantine\t"));
+    assert!(lines[1].starts_with("ok\tmove:Archive\tINBOX\t"));
+    // Archive already holds a copy with the same GUID, the undo moves back the copy just moved,
+    // which has the highest UID
+    assert!(lines[1].ends_with("\tdoveadm move -u duplicates INBOX MAILBOX-GUID mb-archive UID 12"));
+}
+
+#[test]
+fn quarantined_copy_not_found() {
+    let removals = removals(&KeepPolicy::Oldest);
+    let mut remover = remover(
+        "duplicates",
+        RemovalMethod::Quarantine("Quarantine".to_owned()),
+        false,
+    );
+    let summary = remover.remove(&removals).unwrap();
+    assert_eq!(summary.removed, 2);
+
+    // the moves succeeded but the search finds nothing to undo them with
+    let lines = log_lines(remover);
+    assert!(lines[0].starts_with("ok\tmove:Quarantine\t"));
+    assert!(lines[0].ends_with("\t-"));
+}
+
+#[test]
+fn expunge_failures() {
+    let removals = removals(&KeepPolicy::Oldest);
+    let mut remover = remover("expungefail", RemovalMethod::Expunge, false);
+    let summary = remover.remove(&removals).unwrap();
+    assert_eq!((summary.removed, summary.failed), (0, 2));
+
+    let lines = log_lines(remover);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("failed: no such mailbox\texpunge\tArchive\t"));
+    // expunged messages can not be restored
+    assert!(lines[0].ends_with("\t-"));
+}
+
+#[test]
+fn fatal_failure() {
+    let removals = removals(&KeepPolicy::Oldest);
+    let mut remover = remover(
+        "noperm",
+        RemovalMethod::Quarantine("Quarantine".to_owned()),
+        false,
+    );
+    let err = remover.remove(&removals).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<DoveadmError>().unwrap().kind,
+        DoveadmErrorKind::NoPermission
+    );
+    // the removal stops at the first message
+    assert_eq!(log_lines(remover).len(), 1);
+}
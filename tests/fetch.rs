@@ -0,0 +1,405 @@
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use mail_kraken::doveadm::{
+    DoveadmError, DoveadmErrorKind, DoveadmFetch, FetchRecord, ImapField, OutputFormat, Recovery,
+};
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{params, FAKE_DOVEADM};
+
+fn fetch(user: &str, fields: &[ImapField]) -> Result<DoveadmFetch> {
+    DoveadmFetch::with_cmd(FAKE_DOVEADM, params(user, fields))
+}
+
+fn fetch_all(user: &str, fields: &[ImapField]) -> Result<Vec<FetchRecord>> {
+    fetch(user, fields)?.collect()
+}
+
+#[test]
+fn multi_line_headers() {
+    let records = fetch_all(
+        "multiline",
+        &[ImapField::Flags, ImapField::Mailbox, ImapField::Hdr],
+    )
+    .unwrap();
+    assert_eq!(records.len(), 3);
+
+    let record = &records[0];
+    assert!(record.has_flag("\\Seen"));
+    assert_eq!(record.mailbox(), Some("INBOX"));
+    assert_eq!(record.headers().unwrap().len(), 6);
+    assert_eq!(record.headers_all("received").len(), 2);
+    assert_eq!(
+        record.header("Received"),
+        Some(
+            "from mail.example.com (mail.example.com [192.0.2.1])\
+             \tby mx.example.org (Postfix) with ESMTPS id 4F1A2\
+             \tfor <bob@example.org>; Mon, 15 Aug 2022 10:23:45 +0200 (CEST)"
+        )
+    );
+    assert_eq!(record.header("subject"), Some("Hello World"));
+    assert_eq!(record.header_raw("subject"), Some("Hello\n World"));
+    let from = record.addresses("from").unwrap();
+    assert_eq!(from.len(), 1);
+    assert_eq!(
+        from[0].mailboxes()[0].display_name.as_deref(),
+        Some("Alice")
+    );
+    assert_eq!(from[0].mailboxes()[0].normalized(), "alice@example.com");
+
+    let record = &records[1];
+    assert_eq!(record.flags(), Some(&[][..]));
+    assert_eq!(record.mailbox(), Some("Sent Items"));
+    assert_eq!(record.header("Subject"), Some("Re: Hello"));
+
+    let record = &records[2];
+    assert_eq!(record.flags().unwrap().len(), 3);
+    assert_eq!(record.header("To"), Some("bob@example.org"));
+}
+
+#[test]
+fn empty_fields() {
+    let records = fetch_all(
+        "empty",
+        &[ImapField::Guid, ImapField::Flags, ImapField::Hdr],
+    )
+    .unwrap();
+    assert_eq!(records.len(), 2);
+
+    assert_eq!(records[0].guid(), Some(""));
+    assert_eq!(records[0].flags(), Some(&[][..]));
+    assert_eq!(records[0].headers(), Some(&[][..]));
+
+    assert_eq!(records[1].guid(), Some("4c2f3a0d1e8b3c62a50100002b5d8c41"));
+    assert_eq!(records[1].header("subject"), Some("not empty"));
+}
+
+#[test]
+fn truncated_output() {
+    let mut fetch = fetch("truncated", &[ImapField::Flags, ImapField::Hdr]).unwrap();
+    assert!(fetch.next().unwrap().is_ok());
+    assert!(fetch.next().unwrap().is_err());
+    assert!(fetch.next().is_none());
+    assert!(fetch.get_exit_status().unwrap().success());
+}
+
+#[test]
+fn truncated_by_failure() {
+    let mut fetch = fetch("tempfail", &[ImapField::Flags, ImapField::Hdr]).unwrap();
+    assert!(fetch.next().unwrap().is_ok());
+    let err = fetch.next().unwrap().unwrap_err();
+    let err = err.downcast_ref::<DoveadmError>().unwrap();
+    assert_eq!(err.kind, DoveadmErrorKind::TempFail);
+    assert_eq!(err.exit_code, Some(75));
+    assert_eq!(err.stderr_lines.len(), 1);
+    assert!(fetch.next().is_none());
+}
+
+#[test]
+fn drop_kills_unfinished_fetch() {
+    // the slow fixture keeps doveadm running long after its output
+    let started = Instant::now();
+    let first: Vec<FetchRecord> = fetch(
+        "slow",
+        &[ImapField::Flags, ImapField::Mailbox, ImapField::Hdr],
+    )
+    .unwrap()
+    .take(1)
+    .collect::<Result<_>>()
+    .unwrap();
+    assert_eq!(first.len(), 1);
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn unknown_user() {
+    let err = fetch_all("unknown", &[ImapField::Hdr]).unwrap_err();
+    let err = err.downcast_ref::<DoveadmError>().unwrap();
+    assert_eq!(err.kind, DoveadmErrorKind::UnknownUser);
+    assert_eq!(err.stderr_lines, vec!["Error: User doesn't exist"]);
+    assert_eq!(
+        err.args,
+        vec!["fetch", "-f", "pager", "-u", "unknown", "hdr", "ALL"]
+    );
+}
+
+#[test]
+fn iterator_adapters() {
+    let count = fetch(
+        "multiline",
+        &[ImapField::Flags, ImapField::Mailbox, ImapField::Hdr],
+    )
+    .unwrap()
+    .filter_map(|record| record.ok())
+    .filter(|record| record.mailbox() == Some("INBOX"))
+    .count();
+    assert_eq!(count, 2);
+
+    let first: Vec<FetchRecord> = fetch(
+        "multiline",
+        &[ImapField::Flags, ImapField::Mailbox, ImapField::Hdr],
+    )
+    .unwrap()
+    .take(1)
+    .collect::<Result<_>>()
+    .unwrap();
+    assert_eq!(first.len(), 1);
+}
+
+#[test]
+fn tab_format() {
+    let records = fetch_all(
+        "tab",
+        &[
+            ImapField::Flags,
+            ImapField::Mailbox,
+            ImapField::Guid,
+            ImapField::SizePhysical,
+        ],
+    )
+    .unwrap();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].flags().unwrap().len(), 2);
+    assert_eq!(records[0].size_physical(), Some(1024));
+    assert_eq!(records[1].flags(), Some(&[][..]));
+    assert_eq!(records[1].mailbox(), Some("Sent Items"));
+    assert_eq!(records[2].mailbox(), Some("Odd\tName\x01"));
+}
+
+#[test]
+fn tab_format_field_mismatch() {
+    // the header line does not match the requested fields
+    assert!(fetch_all("tab", &[ImapField::Flags, ImapField::Mailbox]).is_err());
+}
+
+#[test]
+fn flow_format() {
+    let mut params = params(
+        "flow",
+        &[
+            ImapField::Flags,
+            ImapField::Mailbox,
+            ImapField::SizePhysical,
+        ],
+    );
+    params.set_format(OutputFormat::Flow);
+    let records = DoveadmFetch::with_cmd(FAKE_DOVEADM, params)
+        .unwrap()
+        .collect::<Result<Vec<FetchRecord>>>()
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].flags().unwrap().len(), 2);
+    assert_eq!(records[0].size_physical(), Some(1024));
+    assert_eq!(records[1].flags(), Some(&[][..]));
+    assert_eq!(records[1].mailbox(), Some("Sent Items"));
+    assert_eq!(records[1].size_physical(), Some(2048));
+}
+
+#[test]
+fn typed_fields() {
+    let records = fetch_all(
+        "fields",
+        &[
+            ImapField::User,
+            ImapField::Uid,
+            ImapField::Seq,
+            ImapField::SizeVirtual,
+            ImapField::HdrField("subject".to_owned()),
+            ImapField::DateReceivedUnixtime,
+        ],
+    )
+    .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].user(), Some("alice@example.com"));
+    assert_eq!(records[0].uid(), Some(17));
+    assert_eq!(records[0].seq(), Some(3));
+    assert_eq!(records[0].size_virtual(), Some(2100));
+    assert_eq!(records[0].header("Subject"), Some("Quarterly numbers"));
+    assert_eq!(
+        records[1]
+            .date(&ImapField::DateReceivedUnixtime)
+            .unwrap()
+            .timestamp(),
+        1660551900
+    );
+    // dates are displayed in the local time zone
+    let received = Local.timestamp_opt(1660551825, 0).unwrap();
+    assert_eq!(
+        records[0].to_string(),
+        format!(
+            "user: alice@example.com\nuid: 17\nseq: 3\nsize.virtual: 2100\nhdr.subject:\n\
+             subject: Quarterly numbers\ndate.received.unixtime: {}\n",
+            received.format("%Y-%m-%d %H:%M:%S %z")
+        )
+    );
+}
+
+#[test]
+fn empty_pop3_order() {
+    // messages not migrated from POP3 have an empty pop3.order
+    let records = fetch_all(
+        "pop3",
+        &[ImapField::Uid, ImapField::Pop3Order, ImapField::Pop3Uidl],
+    )
+    .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].value(&ImapField::Pop3Order), Some("1"));
+    assert_eq!(
+        records[0].value(&ImapField::Pop3Uidl),
+        Some("000001a34f5e8c21")
+    );
+    assert_eq!(records[1].uid(), Some(2));
+    assert_eq!(records[1].value(&ImapField::Pop3Order), Some(""));
+}
+
+#[test]
+fn encoded_headers() {
+    let records = fetch_all("encoded", &[ImapField::Hdr]).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let record = &records[0];
+    assert_eq!(
+        record.header("from"),
+        Some("Jörg Müller <joerg@example.de>")
+    );
+    assert_eq!(
+        record.header_raw("from"),
+        Some("=?UTF-8?Q?J=C3=B6rg_M=C3=BCller?= <joerg@example.de>")
+    );
+    assert_eq!(record.header("subject"), Some("Grüße aus München"));
+    assert_eq!(record.header("to"), Some("bob@example.org"));
+
+    let record = &records[1];
+    assert_eq!(record.header("from"), Some("Привет <ivan@example.ru>"));
+    assert_eq!(record.header("subject"), Some("=?broken?= plain"));
+}
+
+#[test]
+fn non_utf8_data() {
+    let records = fetch_all("8bit", &[ImapField::Mailbox, ImapField::Hdr]).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].mailbox(), Some("Gr\u{fffd}n"));
+    assert_eq!(
+        records[0].header("from"),
+        Some("Jörg Müller <joerg@example.de>")
+    );
+    // each header line is decoded on its own
+    assert_eq!(records[0].header("subject"), Some("Grüße aus München"));
+    assert_eq!(records[1].header("subject"), Some("plain"));
+}
+
+#[test]
+fn skip_malformed_records() {
+    let fields = [ImapField::Flags, ImapField::Mailbox, ImapField::Hdr];
+    assert!(fetch_all("malformed", &fields).is_err());
+
+    let mut fetch = fetch("malformed", &fields).unwrap();
+    fetch.set_recovery(Recovery::Skip);
+    let records = (&mut fetch).collect::<Result<Vec<FetchRecord>>>().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].header("subject"), Some("one"));
+    assert_eq!(records[1].header("subject"), Some("four"));
+
+    let skipped = fetch.skipped();
+    assert_eq!(skipped.len(), 2);
+    assert_eq!((skipped[0].first_line, skipped[0].last_line), (6, 11));
+    assert!(skipped[0].reason.contains("Flags"));
+    // the record ends early, its last line is the form feed of the next record
+    assert_eq!((skipped[1].first_line, skipped[1].last_line), (12, 14));
+}
+
+#[test]
+fn skip_malformed_tab_records() {
+    // a header line that does not match the fields is not skipped
+    let mut mismatch = fetch("malformedtab", &[ImapField::Flags, ImapField::Mailbox]).unwrap();
+    mismatch.set_recovery(Recovery::Skip);
+    assert!(mismatch.next().unwrap().is_err());
+
+    let mut fetch = fetch(
+        "malformedtab",
+        &[
+            ImapField::Flags,
+            ImapField::Mailbox,
+            ImapField::SizePhysical,
+        ],
+    )
+    .unwrap();
+    fetch.set_recovery(Recovery::Skip);
+    let records = (&mut fetch).collect::<Result<Vec<FetchRecord>>>().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].size_physical(), Some(2048));
+    let skipped = fetch.skipped();
+    assert_eq!(skipped.len(), 2);
+    assert_eq!((skipped[0].first_line, skipped[0].last_line), (3, 3));
+    assert_eq!((skipped[1].first_line, skipped[1].last_line), (4, 4));
+}
+
+#[test]
+fn mime_body() {
+    let records = fetch_all("mime", &[ImapField::Uid, ImapField::Hdr, ImapField::Body]).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let message = records[0].mime().unwrap();
+    assert_eq!(message.content_type.value, "multipart/mixed");
+    assert_eq!(message.parts.len(), 2);
+    assert_eq!(message.parts[0].text().as_deref(), Some("Grüße"));
+    let image = &message.parts[1];
+    assert!(image.is_attachment());
+    assert_eq!(image.filename(), Some("dot.png"));
+    assert_eq!(image.body, b"\x89PNG\r\n\x1a\n");
+
+    let message = records[1].mime().unwrap();
+    assert_eq!(message.text().as_deref(), Some("Just text.\n"));
+    assert!(records[1].content(&ImapField::Text).is_none());
+}
+
+#[test]
+fn bodystructure() {
+    let records = fetch_all(
+        "bodystructure",
+        &[ImapField::Uid, ImapField::ImapBodystructure],
+    )
+    .unwrap();
+    assert_eq!(records.len(), 2);
+
+    let body = records[0].bodystructure().unwrap();
+    assert_eq!(body.content_type.value, "text/plain");
+    assert_eq!(body.lines, Some(4));
+
+    // the name parameter is a literal, the value continues on the next line
+    let body = records[1].bodystructure().unwrap();
+    assert_eq!(body.parts.len(), 2);
+    let attachments: Vec<_> = body
+        .leaves()
+        .into_iter()
+        .filter(|part| part.is_attachment())
+        .collect();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(
+        attachments[0].content_type.param("name"),
+        Some("photo .jpg")
+    );
+    assert_eq!(attachments[0].filename(), Some("photo.jpg"));
+    assert_eq!(attachments[0].decoded_size(), 30000);
+}
+
+#[test]
+fn envelope() {
+    let records = fetch_all("envelope", &[ImapField::Uid, ImapField::ImapEnvelope]).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let envelope = records[0].envelope().unwrap();
+    assert_eq!(envelope.subject.as_deref(), Some("Hello"));
+    assert_eq!(
+        envelope.from_mailbox().unwrap().normalized(),
+        "alice@example.com"
+    );
+    assert_eq!(envelope.to[0].mailboxes()[0].addr_spec(), "bob@example.org");
+    assert_eq!(envelope.message_id.as_deref(), Some("<1@example.com>"));
+
+    // the subject is a literal spanning two lines
+    let envelope = records[1].envelope().unwrap();
+    assert_eq!(envelope.subject.as_deref(), Some("multi\nline re"));
+    assert!(envelope.from.is_empty());
+}
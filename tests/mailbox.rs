@@ -0,0 +1,54 @@
+use mail_kraken::analysis::FolderReport;
+use mail_kraken::doveadm::{DoveadmError, DoveadmErrorKind, DoveadmMailboxList};
+
+mod common;
+use common::FAKE_DOVEADM;
+
+fn mailbox_list(user: &str) -> DoveadmMailboxList {
+    DoveadmMailboxList::with_cmd(FAKE_DOVEADM, user.to_owned())
+}
+
+#[test]
+fn list() {
+    assert_eq!(
+        mailbox_list("folders").list().unwrap(),
+        vec!["INBOX", "Archive", "Sent Items", "Gone", "Trash"]
+    );
+}
+
+#[test]
+fn status() {
+    let info = mailbox_list("folders").status("Sent Items").unwrap();
+    assert_eq!(info.name, "Sent Items");
+    assert_eq!(info.guid, "mb-sent");
+    assert_eq!((info.messages, info.vsize, info.uid_next), (12, 56000, 13));
+
+    let err = mailbox_list("folders").status("Gone").unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<DoveadmError>().unwrap().kind,
+        DoveadmErrorKind::NoSuchMailbox
+    );
+}
+
+#[test]
+fn mailboxes() {
+    // Gone was deleted between list and status
+    let mailboxes = mailbox_list("folders").mailboxes().unwrap();
+    let names: Vec<&str> = mailboxes.iter().map(|mb| mb.name.as_str()).collect();
+    assert_eq!(names, vec!["INBOX", "Archive", "Sent Items", "Trash"]);
+
+    let report = FolderReport::new(mailboxes);
+    assert_eq!(report.sorted()[0].name, "Archive");
+    assert!(report
+        .to_string()
+        .contains("4 mailboxes, 135 messages of 3468045 bytes"));
+}
+
+#[test]
+fn unknown_user() {
+    let err = mailbox_list("unknown").list().unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<DoveadmError>().unwrap().kind,
+        DoveadmErrorKind::UnknownUser
+    );
+}
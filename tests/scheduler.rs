@@ -0,0 +1,245 @@
+use mail_kraken::doveadm::{
+    DoveadmError, DoveadmErrorKind, DoveadmFetch, FetchEvent, FetchParams, FetchScheduler,
+    ImapField, SearchParam, ShardedFetch,
+};
+
+mod common;
+use common::{counting_doveadm, fetch_log, params, FAKE_DOVEADM};
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// the fields of the tab fixture
+const FIELDS: [ImapField; 4] = [
+    ImapField::Flags,
+    ImapField::Mailbox,
+    ImapField::Guid,
+    ImapField::SizePhysical,
+];
+
+// the guids fetched by every job, the error kind for failed jobs
+fn run(users: &[&str], jobs: usize, per_user: usize) -> Vec<Result<Vec<String>, DoveadmErrorKind>> {
+    let mut res: Vec<Result<Vec<String>, DoveadmErrorKind>> = Vec::new();
+    let mut current = Vec::new();
+    let mut expected_job = 0;
+    for event in FetchScheduler::with_cmd(FAKE_DOVEADM)
+        .set_jobs(jobs)
+        .set_per_user(per_user)
+        .run(users.iter().map(|user| params(user, &FIELDS)))
+    {
+        match event {
+            FetchEvent::Record(job, record) => {
+                assert_eq!(job, expected_job);
+                current.push(record.guid().unwrap().to_owned());
+            }
+            FetchEvent::Done(job, skipped) => {
+                assert_eq!(job, expected_job);
+                assert!(skipped.is_empty());
+                res.push(Ok(std::mem::take(&mut current)));
+                expected_job += 1;
+            }
+            FetchEvent::Failed(job, err) => {
+                assert_eq!(job, expected_job);
+                res.push(Err(err.downcast_ref::<DoveadmError>().unwrap().kind));
+                current.clear();
+                expected_job += 1;
+            }
+        }
+    }
+    res
+}
+
+#[test]
+fn events_in_job_order() {
+    let users = ["tab", "unknown", "tab", "tab"];
+    let guids: Vec<String> = DoveadmFetch::with_cmd(FAKE_DOVEADM, params("tab", &FIELDS))
+        .unwrap()
+        .map(|record| record.unwrap().guid().unwrap().to_owned())
+        .collect();
+    assert_eq!(guids.len(), 3);
+
+    let sequential = run(&users, 1, 1);
+    assert_eq!(
+        sequential,
+        vec![
+            Ok(guids.clone()),
+            Err(DoveadmErrorKind::UnknownUser),
+            Ok(guids.clone()),
+            Ok(guids),
+        ]
+    );
+    // concurrent fetches deliver the same events in the same order
+    assert_eq!(run(&users, 4, 2), sequential);
+    assert_eq!(run(&users, 3, 1), sequential);
+}
+
+#[test]
+fn no_jobs() {
+    assert!(run(&[], 4, 1).is_empty());
+}
+
+// the maximum number of fetches running at once, in total and per user, from the log of
+// counting_doveadm
+fn max_running(log: &[String]) -> (usize, HashMap<String, usize>) {
+    let mut running = 0;
+    let mut max = 0;
+    let mut running_per_user: HashMap<String, usize> = HashMap::new();
+    let mut max_per_user: HashMap<String, usize> = HashMap::new();
+    for line in log {
+        let (event, user) = match line.split(' ').collect::<Vec<&str>>()[..] {
+            [event, "fetch", user] => (event, user),
+            _ => panic!("unexpected log line '{}'", line),
+        };
+        let user_running = running_per_user.entry(user.to_owned()).or_default();
+        if event == "start" {
+            running += 1;
+            *user_running += 1;
+        } else {
+            running -= 1;
+            *user_running -= 1;
+        }
+        max = max.max(running);
+        let user_max = max_per_user.entry(user.to_owned()).or_default();
+        *user_max = (*user_max).max(*user_running);
+    }
+    (max, max_per_user)
+}
+
+// the fetch log of running users with the limits, the events are consumed as fast as possible
+fn concurrency(test: &str, users: &[&str], jobs: usize, per_user: usize) -> Vec<String> {
+    let (cmd, log) = counting_doveadm(test);
+    let done = FetchScheduler::with_cmd(cmd)
+        .set_jobs(jobs)
+        .set_per_user(per_user)
+        .run(users.iter().map(|user| params(user, &FIELDS)))
+        .filter(|event| matches!(event, FetchEvent::Done(..)))
+        .count();
+    assert_eq!(done, users.len());
+    fetch_log(&log)
+}
+
+#[test]
+fn concurrency_limits() {
+    let users = ["tab", "tab", "tab", "tab2", "tab2", "tab"];
+    let log = concurrency("limits_per_user", &users, 3, 1);
+    assert_eq!(log.len(), 2 * users.len());
+    let (max, max_per_user) = max_running(&log);
+    assert_eq!(max, 2);
+    assert_eq!(max_per_user["tab"], 1);
+    assert_eq!(max_per_user["tab2"], 1);
+
+    let (max, max_per_user) = max_running(&concurrency("limits_jobs", &users, 3, 3));
+    assert_eq!(max, 3);
+    assert_eq!(max_per_user["tab"], 3);
+}
+
+#[test]
+fn jobs_ahead_of_consumer() {
+    let jobs = 2;
+    let (cmd, log) = counting_doveadm("jobs_ahead");
+    let mut consumed = 0;
+    for event in FetchScheduler::with_cmd(cmd)
+        .set_jobs(jobs)
+        .set_per_user(jobs)
+        .run((0..6).map(|_| params("tab", &FIELDS)))
+    {
+        if let FetchEvent::Done(..) = event {
+            consumed += 1;
+            // a slow consumer, the fetches ahead of it have long finished
+            thread::sleep(Duration::from_millis(500));
+            let started = fetch_log(&log)
+                .iter()
+                .filter(|line| line.starts_with("start"))
+                .count();
+            assert!(
+                started <= consumed + jobs,
+                "{} fetches started with {} jobs consumed",
+                started,
+                consumed
+            );
+        }
+    }
+    assert_eq!(consumed, 6);
+}
+
+// the number of fetches run for the job of user with retries, and its events
+fn retried(test: &str, user: &str, retries: usize) -> (usize, Vec<FetchEvent>) {
+    let (cmd, log) = counting_doveadm(test);
+    let events = FetchScheduler::with_cmd(cmd)
+        .set_retries(retries)
+        .set_retry_delay(RETRY_DELAY)
+        .run(vec![params(user, &[ImapField::Flags, ImapField::Hdr])])
+        .collect();
+    let fetches = fetch_log(&log)
+        .iter()
+        .filter(|line| line.starts_with("start"))
+        .count();
+    (fetches, events)
+}
+
+#[test]
+fn retry_unsharded() {
+    // failed before the first record
+    let (fetches, events) = retried("retry_busy", "busy", 2);
+    assert_eq!(fetches, 3);
+    assert!(matches!(events[..], [FetchEvent::Failed(0, _)]));
+
+    // records already passed on can not be taken back
+    let (fetches, events) = retried("retry_tempfail", "tempfail", 2);
+    assert_eq!(fetches, 1);
+    assert!(matches!(
+        events[..],
+        [FetchEvent::Record(0, _), FetchEvent::Failed(0, _)]
+    ));
+}
+
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// the shards fixtures hold the UIDs 1 to 6, UID 3 is expunged and the fetch of the UIDs from 5
+// on fails temporarily after the first record. UID 5 is delivered after uid_next 5 was read.
+fn sharded(uid_next: u64, jobs: usize, retries: usize) -> (Vec<u64>, Option<DoveadmErrorKind>) {
+    let mut params = FetchParams::new("shards".to_owned());
+    params
+        .add_field(ImapField::Uid)
+        .add_field(ImapField::Guid)
+        .add_search_param(SearchParam::Mailbox("INBOX".to_owned()));
+    let mut scheduler = FetchScheduler::with_cmd(FAKE_DOVEADM);
+    scheduler
+        .set_jobs(jobs)
+        .set_per_user(jobs)
+        .set_retries(retries)
+        .set_retry_delay(RETRY_DELAY);
+    let fetch = ShardedFetch::new(&scheduler, &params, uid_next, 2);
+
+    let mut uids = Vec::new();
+    for record in fetch {
+        match record {
+            Ok(record) => uids.push(record.uid().unwrap()),
+            Err(err) => return (uids, Some(err.downcast_ref::<DoveadmError>().unwrap().kind)),
+        }
+    }
+    (uids, None)
+}
+
+#[test]
+fn sharded_fetch() {
+    // the last shard is open-ended and picks up the messages delivered in the meantime
+    assert_eq!(sharded(5, 1, 0), (vec![1, 2, 4, 5], None));
+    assert_eq!(sharded(5, 2, 1), (vec![1, 2, 4, 5], None));
+}
+
+#[test]
+fn sharded_fetch_failure() {
+    // the records of the failed shard are passed on unless they are held back for a retry
+    assert_eq!(
+        sharded(7, 3, 0),
+        (vec![1, 2, 4, 5], Some(DoveadmErrorKind::TempFail))
+    );
+    let started = Instant::now();
+    assert_eq!(
+        sharded(7, 3, 2),
+        (vec![1, 2, 4], Some(DoveadmErrorKind::TempFail))
+    );
+    // the second retry waits twice as long as the first
+    assert!(started.elapsed() >= RETRY_DELAY * 3);
+}
@@ -0,0 +1,136 @@
+use mail_kraken::doveadm::{DoveadmError, DoveadmErrorKind, DoveadmUserList};
+use mail_kraken::{analyse, CmdArgs};
+use structopt::StructOpt;
+
+mod common;
+use common::{counting_doveadm, fetch_log, FAKE_DOVEADM};
+
+#[test]
+fn list_users() {
+    let users = DoveadmUserList::with_cmd(FAKE_DOVEADM);
+    assert_eq!(
+        users.list("*").unwrap(),
+        vec!["alice@example.com", "bob@example.com", "unknown"]
+    );
+    // a mask without wildcards is a lookup of that user
+    assert_eq!(
+        users.list("alice@example.com").unwrap(),
+        vec!["alice@example.com"]
+    );
+    let err = users.list("nobody@example.com").unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<DoveadmError>().unwrap().kind,
+        DoveadmErrorKind::UnknownUser
+    );
+}
+
+#[test]
+fn analyse_all_users() {
+    let cmd_args = CmdArgs::from_iter([
+        "analyse",
+        "sizes",
+        "--top",
+        "2",
+        "--all-users",
+        "--recovery",
+        "skip",
+        "--doveadm",
+        FAKE_DOVEADM,
+    ]);
+    let mut out = Vec::new();
+    analyse(&cmd_args, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    let alice = out.find("user alice@example.com:").unwrap();
+    let bob = out.find("user bob@example.com:").unwrap();
+    let unknown = out.find("user unknown:").unwrap();
+    let rollup = out.find("all 3 users:").unwrap();
+    assert!(alice < bob && bob < unknown && unknown < rollup);
+    assert!(out[alice..bob].contains("2 messages of 2005000 bytes"));
+    assert!(out[bob..unknown].contains("skipped 1 malformed records"));
+
+    // the unknown user fails, the analysis continues and the failure is listed at the end
+    assert!(out[unknown..rollup].contains("error: "));
+    let rollup = &out[rollup..];
+    assert!(rollup.contains("\n4 messages of 5155000 bytes"));
+    let backup = rollup.find(" Backup").unwrap();
+    let video = rollup.find(" Video").unwrap();
+    assert!(backup < video);
+    assert!(!rollup.contains(" Small"));
+    // skipped records are counted for all users too
+    assert!(rollup.contains("\nskipped 1 malformed records\n"));
+    assert!(rollup.contains("1 users failed:\nunknown: "));
+}
+
+#[test]
+fn analyse_as_tsv() {
+    let cmd_args = CmdArgs::from_iter([
+        "analyse",
+        "sizes",
+        "--top",
+        "2",
+        "--all-users",
+        "--recovery",
+        "skip",
+        "--output",
+        "tsv",
+        "--doveadm",
+        FAKE_DOVEADM,
+    ]);
+    let mut out = Vec::new();
+    analyse(&cmd_args, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    let rollup = &out[out.find("all 3 users:").unwrap()..];
+    assert!(rollup.contains("\nsize\tcount\ttotal\n< 10k\t1\t5000\n"));
+    assert!(rollup.contains(
+        "\nuser\tmailbox\tuid\tdate\tsize\tsubject\n\
+         bob@example.com\tINBOX\t1\t2022-09-01\t3000000\tBackup\n"
+    ));
+    assert!(rollup.contains("\ntotal\tvalue\nmessages\t4\nsize\t5155000\n"));
+    assert!(rollup.contains("\ntotal\tvalue\nskipped\t1\n"));
+
+    // the fetched records have a layout of their own
+    let cmd_args = CmdArgs::from_iter([
+        "analyse",
+        "fetch",
+        "-f",
+        "uid",
+        "-u",
+        "alice@example.com",
+        "--output",
+        "tsv",
+        "--doveadm",
+        FAKE_DOVEADM,
+    ]);
+    assert!(analyse(&cmd_args, &mut Vec::new()).is_err());
+}
+
+#[test]
+fn shard_users_lazily() {
+    let (cmd, log) = counting_doveadm("shard_users_lazily");
+    let cmd_args = CmdArgs::from_iter([
+        "analyse",
+        "sizes",
+        "--all-users",
+        "--recovery",
+        "skip",
+        "--shard-size",
+        "100",
+        "--doveadm",
+        cmd.to_str().unwrap(),
+    ]);
+    let mut out = Vec::new();
+    analyse(&cmd_args, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("\n4 messages of 5155000 bytes"));
+
+    // the UIDs of a user's mailbox are looked up when the user is reached, not up front
+    let log = fetch_log(&log);
+    let position = |line: &str| log.iter().position(|entry| entry == line).unwrap();
+    assert!(
+        position("end fetch alice@example.com") < position("start mailbox bob@example.com"),
+        "{:?}",
+        log
+    );
+}